@@ -1,7 +1,31 @@
 use crate::common::*;
 use crate::{D, P};
 use std::fmt;
-use std::ops::{Index, IndexMut};
+
+/// Maximum number of pieces of a kind in a hand, plus one.
+const HAND_LIMIT: usize = 19;
+
+const fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+const fn zobrist_table<const N: usize>(seed: u64) -> [u64; N] {
+    let mut ret = [0; N];
+    let mut i = 0;
+    while i < N {
+        ret[i] = splitmix64(seed.wrapping_mul(0x1000193).wrapping_add(i as u64));
+        i += 1;
+    }
+    ret
+}
+
+/// Zobrist keys for (cell, piece) pairs, where pieces are indexed by `SidedPiece.0 + 16`.
+static ZOBRIST_PIECES: [u64; BOARD_CELLS as usize * 32] = zobrist_table(1);
+/// Zobrist keys for (player, piece, count) triples of hands.
+static ZOBRIST_HANDS: [u64; 2 * PIECE_TYPES * HAND_LIMIT] = zobrist_table(2);
 
 #[derive(Clone)]
 pub struct Board {
@@ -19,7 +43,7 @@ impl Board {
         }
     }
     pub fn is_inside_board(&self, pos: P) -> bool {
-        0 <= pos.0 && pos.0 < BOARD_SIZE as i32 && 0 <= pos.1 && pos.1 < BOARD_SIZE as i32
+        0 <= pos.0 && pos.0 < BOARD_SIZE && 0 <= pos.1 && pos.1 < BOARD_SIZE
     }
     pub fn get_sided_piece(&self, pos: P) -> SidedPiece {
         assert!(self.is_inside_board(pos));
@@ -41,19 +65,45 @@ impl Board {
     pub fn get_second_hand(&self, piece: Piece) -> i8 {
         self.hand_second[piece.0 as usize]
     }
+    /// Computes the Zobrist hash of the position, including both hands.
+    pub fn hash(&self) -> u64 {
+        let mut ret = self.board_hash();
+        for p in 0..PIECE_TYPES {
+            ret ^= ZOBRIST_HANDS[p * HAND_LIMIT + self.hand_first[p] as usize];
+            ret ^= ZOBRIST_HANDS[(PIECE_TYPES + p) * HAND_LIMIT + self.hand_second[p] as usize];
+        }
+        ret
+    }
+    /// Computes the Zobrist hash of the pieces on the board, excluding the hands.
+    pub fn board_hash(&self) -> u64 {
+        let mut ret = 0;
+        for i in 0..(BOARD_CELLS as usize) {
+            let piece = self.pieces[i];
+            if !piece.is_empty() {
+                ret ^= ZOBRIST_PIECES[i * 32 + (piece.0 + 16) as usize];
+            }
+        }
+        ret
+    }
     pub fn locate_second_king(&self) -> P {
+        self.find_second_king().unwrap()
+    }
+    pub fn find_second_king(&self) -> Option<P> {
         for y in 0..BOARD_SIZE {
             for x in 0..BOARD_SIZE {
                 if self.get_sided_piece(P(y, x)) == SECOND_KING {
-                    return P(y, x);
+                    return Some(P(y, x));
                 }
             }
         }
-        panic!();
+        None
     }
     pub fn is_check(&self) -> bool {
-        let king_pos = self.locate_second_king();
-
+        self.is_attacked_by_first(self.locate_second_king())
+    }
+    /// Returns whether a piece of the first player attacks `king_pos`, ignoring the piece on it.
+    #[allow(clippy::needless_range_loop)]
+    pub fn is_attacked_by_first(&self, king_pos: P) -> bool {
         for i in 0..(PIECE_TYPES * 2) {
             for j in 0..PIECE_MOVES_COUNT {
                 if PIECE_MOVES[i][j] == D(0, 0) {
@@ -177,13 +227,28 @@ impl Board {
             if (!is_second && pos.0 == 0) || (is_second && pos.0 == 8) {
                 return false;
             }
-        } else if piece == PIECE_KNIGHT {
-            if (!is_second && pos.0 <= 1) || (is_second && pos.0 >= 7) {
-                return false;
-            }
+        } else if piece == PIECE_KNIGHT && ((!is_second && pos.0 <= 1) || (is_second && pos.0 >= 7))
+        {
+            return false;
         }
         true
     }
+    /// Returns whether the player can drop `piece` from its hand on `pos`: the piece is in hand,
+    /// the cell is empty, the piece can move afterwards, and a pawn is not dropped on a file
+    /// with another pawn of the player (nifu).
+    pub fn can_drop(&self, pos: P, piece: Piece, is_second: bool) -> bool {
+        let (sided, n) = if is_second {
+            (piece.as_second(), self.get_second_hand(piece))
+        } else {
+            (piece.as_first(), self.get_first_hand(piece))
+        };
+        n > 0
+            && self.get_sided_piece(pos).is_empty()
+            && Board::has_further_move(pos, piece, is_second)
+            && !(piece == PIECE_PAWN
+                && (0..BOARD_SIZE).any(|y| self.get_sided_piece(P(y, pos.x())) == sided))
+    }
+    #[allow(clippy::needless_range_loop)]
     pub fn enumerate_moves(&self, pos: P) -> Vec<Move> {
         let mut ret = vec![];
         let piece = self.get_sided_piece(pos);
@@ -271,6 +336,7 @@ impl Board {
             }
         }
     }
+    #[allow(clippy::needless_range_loop)]
     pub fn enumerate_check(&self) -> Vec<Move> {
         // TODO: more efficient algorithm
         let mut ret = vec![];
@@ -280,7 +346,6 @@ impl Board {
                 if !piece.is_first() {
                     continue;
                 }
-                let piece = piece.to_piece();
                 let moves = self.enumerate_moves(P(y, x));
 
                 for &mv in &moves {
@@ -391,22 +456,109 @@ impl Board {
         }
     }
     pub fn enumerate_check_avoidance(&self) -> Vec<Move> {
-        // TODO: more efficient algorithm
+        let mut ret = vec![];
+        self.visit_check_avoidance(&mut |mv| {
+            ret.push(mv);
+            false
+        });
+        ret
+    }
+    /// Returns the moves of the first player, including the drops on every empty cell where
+    /// the piece can move afterwards. The first player's king, if any, is not considered.
+    pub fn enumerate_first_moves(&self) -> Vec<Move> {
+        self.enumerate_player_moves(false)
+    }
+    /// Returns the moves of the second player which don't leave its king in check,
+    /// including the drops on every empty cell where the piece can move afterwards.
+    pub fn enumerate_second_moves(&self) -> Vec<Move> {
+        let mut ret = self.enumerate_player_moves(true);
+        ret.retain(|&mv| {
+            let mut board = self.clone();
+            board.apply_move(mv);
+            !board.is_check()
+        });
+        ret
+    }
+    /// Returns the moves of the given player, including the legal drops, without checking
+    /// whether they leave its king in check.
+    pub(crate) fn enumerate_player_moves(&self, is_second: bool) -> Vec<Move> {
+        let is_own = |piece: SidedPiece| {
+            if is_second {
+                piece.is_second()
+            } else {
+                piece.is_first()
+            }
+        };
         let mut ret = vec![];
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                if is_own(self.get_sided_piece(P(y, x))) {
+                    ret.extend(self.enumerate_moves(P(y, x)));
+                }
+            }
+        }
+        let hand = if is_second {
+            &self.hand_second
+        } else {
+            &self.hand_first
+        };
+        for (i, &count) in hand.iter().enumerate().take(PIECE_TYPES - 1) {
+            if count == 0 {
+                continue;
+            }
+            let piece = Piece(i as i8);
+            let sided = if is_second {
+                piece.as_second()
+            } else {
+                piece.as_first()
+            };
+            for y in 0..BOARD_SIZE {
+                for x in 0..BOARD_SIZE {
+                    if self.can_drop(P(y, x), piece, is_second) {
+                        ret.push(Move::FromHand(P(y, x), sided));
+                    }
+                }
+            }
+        }
+        ret
+    }
+    /// Returns whether the second player has a move avoiding the check.
+    /// Escapes of the king are tested first, since they are the most common evasions.
+    pub fn has_check_avoidance(&self) -> bool {
+        !self.king_flights().is_empty() || self.visit_check_avoidance(&mut |_| true)
+    }
+    /// Returns the cells next to the second king where it can move without being in check.
+    pub fn king_flights(&self) -> Vec<P> {
+        let king_pos = self.locate_second_king();
+        let mut lifted = self.clone();
+        lifted.set_sided_piece(king_pos, EMPTY_CELL);
+        PIECE_MOVES[PIECE_KING.0 as usize]
+            .iter()
+            .map(|&d| king_pos + d)
+            .filter(|&pos| {
+                self.is_inside_board(pos)
+                    && !self.get_sided_piece(pos).is_second()
+                    && !lifted.is_attacked_by_first(pos)
+            })
+            .collect()
+    }
+    /// Calls `visit` for each move avoiding the check until it returns `true`.
+    /// Returns whether it is stopped by `visit`.
+    fn visit_check_avoidance(&self, visit: &mut dyn FnMut(Move) -> bool) -> bool {
+        // TODO: more efficient algorithm
         for y in 0..BOARD_SIZE {
             for x in 0..BOARD_SIZE {
                 let piece = self.get_sided_piece(P(y, x));
                 if !piece.is_second() {
                     continue;
                 }
-                let piece = piece.to_piece();
                 let moves = self.enumerate_moves(P(y, x));
 
                 for &mv in &moves {
                     let mut board = self.clone();
                     board.apply_move(mv);
-                    if !board.is_check() {
-                        ret.push(mv);
+                    if !board.is_check() && visit(mv) {
+                        return true;
                     }
                 }
             }
@@ -457,18 +609,133 @@ impl Board {
                                 Move::FromHand(king_pos + d * i, Piece(p as i8).as_second());
                             let mut board = self.clone();
                             board.apply_move(move_cand);
-                            if !board.is_check() {
-                                ret.push(move_cand);
+                            if !board.is_check() && visit(move_cand) {
+                                return true;
                             }
                         }
                     }
                 }
             }
         }
-        ret
+        false
     }
     pub fn is_checkmate(&self) -> bool {
-        self.enumerate_check_avoidance().len() == 0
+        !self.has_check_avoidance()
+    }
+    /// Returns the first check (in the order of `enumerate_check`) which mates immediately.
+    pub fn mate_in_1(&self) -> Option<Move> {
+        self.find_mate_in_1(&self.enumerate_check(), |board| {
+            !board.has_check_avoidance()
+        })
+    }
+    /// Returns the first check among `checks` after which `is_mate` holds.
+    /// `is_mate` is not called for drops after which the king can obviously escape: a drop
+    /// blocks attacks but never adds ones except its own, so a cell where the king can
+    /// move now remains a flight unless the dropped piece attacks it.
+    pub(crate) fn find_mate_in_1(
+        &self,
+        checks: &[Move],
+        mut is_mate: impl FnMut(&Board) -> bool,
+    ) -> Option<Move> {
+        let king_pos = self.locate_second_king();
+        let mut lifted = self.clone();
+        lifted.set_sided_piece(king_pos, EMPTY_CELL);
+        let flights = self.king_flights();
+
+        checks.iter().copied().find(|&mv| {
+            if let Move::FromHand(dest, piece) = mv {
+                if flights
+                    .iter()
+                    .any(|&pos| pos != dest && !lifted.is_attacked_from(dest, piece, pos))
+                {
+                    return false;
+                }
+            }
+            let mut board = self.clone();
+            board.apply_move(mv);
+            is_mate(&board)
+        })
+    }
+    /// Returns the first move of a mate within 3 plies. Mates in 1 are preferred, and otherwise
+    /// the first check (in the order of `enumerate_check`) against which every evasion is
+    /// answered by a mate in 1 is taken.
+    pub fn mate_in_3(&self) -> Option<Move> {
+        self.find_mate_in_3(&self.enumerate_check())
+    }
+    /// Same as `mate_in_3`, but among `checks`.
+    pub(crate) fn find_mate_in_3(&self, checks: &[Move]) -> Option<Move> {
+        if let Some(mv) = self.find_mate_in_1(checks, |board| !board.has_check_avoidance()) {
+            return Some(mv);
+        }
+        checks.iter().copied().find(|&mv| {
+            let mut board = self.clone();
+            board.apply_move(mv);
+            board.is_mated_in_2()
+        })
+    }
+    /// Returns whether every evasion of the second player is answered by a mate in 1.
+    /// The escapes of the king are tried before the other evasions are generated, and then
+    /// the captures of the checker, since they refute the mate most often.
+    fn is_mated_in_2(&self) -> bool {
+        let king_pos = self.locate_second_king();
+        for pos in self.king_flights() {
+            let mut board = self.clone();
+            board.apply_move(Move::NoPromotion(king_pos, pos));
+            if board.mate_in_1().is_none() {
+                return false;
+            }
+        }
+
+        let mut evasions = self.enumerate_check_avoidance();
+        evasions.retain(|mv| match mv {
+            Move::NoPromotion(src, _) | Move::Promotion(src, _) => *src != king_pos,
+            Move::FromHand(..) => true,
+        });
+        // a capture while in check always captures the checker
+        evasions.sort_by_key(|mv| self.get_sided_piece(mv.dest()).is_empty());
+        evasions.into_iter().all(|evasion| {
+            let mut board = self.clone();
+            board.apply_move(evasion);
+            board.mate_in_1().is_some()
+        })
+    }
+    /// Returns whether `piece` of the first player placed on `src` would attack `dest`.
+    fn is_attacked_from(&self, src: P, piece: SidedPiece, dest: P) -> bool {
+        let piece = piece.to_piece();
+        let diff = dest - src;
+        if PIECE_MOVES[piece.0 as usize]
+            .iter()
+            .any(|&d| d != D(0, 0) && d == diff)
+        {
+            return true;
+        }
+        let dirs: &[D] = match piece.capture() {
+            PIECE_LANCE if piece == PIECE_LANCE => &[D(-1, 0)],
+            PIECE_BISHOP => &[D(-1, -1), D(-1, 1), D(1, -1), D(1, 1)],
+            PIECE_ROOK => &[D(-1, 0), D(0, -1), D(0, 1), D(1, 0)],
+            _ => &[],
+        };
+        for &dir in dirs {
+            for i in 1..BOARD_SIZE {
+                let pos = src + dir * i;
+                if !self.is_inside_board(pos) {
+                    break;
+                }
+                if pos == dest {
+                    return true;
+                }
+                if !self.get_sided_piece(pos).is_empty() {
+                    break;
+                }
+            }
+        }
+        false
+    }
+}
+
+impl Default for Board {
+    fn default() -> Board {
+        Board::new()
     }
 }
 
@@ -495,6 +762,56 @@ impl fmt::Display for Board {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_hash() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 5), PIECE_GOLD.as_first());
+        let h = board.hash();
+
+        let mut board2 = board.clone();
+        board2.apply_move(Move::NoPromotion(P(2, 5), P(1, 5)));
+        assert_ne!(board2.hash(), h);
+        board2.apply_move(Move::NoPromotion(P(1, 5), P(2, 5)));
+        assert_eq!(board2.hash(), h);
+
+        board2.set_sided_piece(P(2, 5), PIECE_GOLD.as_second());
+        assert_ne!(board2.hash(), h);
+
+        let mut board3 = board.clone();
+        board3.set_first_hand(PIECE_PAWN, 1);
+        let mut board4 = board.clone();
+        board4.set_second_hand(PIECE_PAWN, 1);
+        assert_ne!(board3.hash(), h);
+        assert_ne!(board3.hash(), board4.hash());
+        assert_eq!(board3.board_hash(), board.board_hash());
+    }
+
+    #[test]
+    fn test_enumerate_second_moves() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 1), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(4, 4), PIECE_PAWN.as_second());
+        board.set_second_hand(PIECE_PAWN, 1);
+        board.set_second_hand(PIECE_KNIGHT, 1);
+
+        let moves = board.enumerate_second_moves();
+        // the king can't move next to the gold
+        let king_moves = moves.iter().filter(|mv| mv.src() == Some(P(0, 0)));
+        assert_eq!(king_moves.count(), 1);
+        assert!(moves.contains(&Move::NoPromotion(P(4, 4), P(5, 4))));
+        let drops = |piece: Piece| {
+            moves
+                .iter()
+                .filter(|&&mv| mv == Move::FromHand(mv.dest(), piece.as_second()))
+                .count()
+        };
+        // neither on the file of the other pawn nor on the last rank
+        assert_eq!(drops(PIECE_PAWN), 8 * 8 - 2);
+        assert_eq!(drops(PIECE_KNIGHT), 9 * 7 - 3);
+    }
+
     #[test]
     fn test_enumerate_check() {
         let mut board = Board::new();
@@ -531,4 +848,92 @@ mod tests {
             assert!(checks.contains(&Move::NoPromotion(P(8, 2), P(5, 5))));
         }
     }
+
+    #[test]
+    fn test_mate_in_1() {
+        // ５二金打, while ５二歩成 and ４二金打 are captured by the king
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_PAWN.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+        assert_eq!(
+            board.mate_in_1(),
+            Some(Move::FromHand(P(1, 4), PIECE_GOLD.as_first()))
+        );
+        assert_eq!(board.mate_in_3(), board.mate_in_1());
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+        assert_eq!(board.mate_in_1(), None);
+        assert_eq!(board.mate_in_3(), Some(Move::NoPromotion(P(1, 5), P(1, 6))));
+    }
+
+    /// Positions with a few pieces placed pseudo-randomly around the second king.
+    fn random_boards(count: usize) -> Vec<Board> {
+        let mut seed = 12345u64;
+        let mut next = |n: i32| {
+            seed = seed
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((seed >> 33) % n as u64) as i32
+        };
+        let mut ret = vec![];
+        while ret.len() < count {
+            let mut board = Board::new();
+            let king_pos = P(next(3), next(BOARD_SIZE));
+            board.set_sided_piece(king_pos, PIECE_KING.as_second());
+            for i in 0..5 {
+                let pos = P(king_pos.0 + next(5) - 1, king_pos.1 + next(5) - 2);
+                if !board.is_inside_board(pos) || !board.get_sided_piece(pos).is_empty() {
+                    continue;
+                }
+                let piece = Piece(next(PIECE_TYPES as i32 - 1) as i8);
+                if i == 0 {
+                    board.set_sided_piece(pos, piece.as_second());
+                } else {
+                    board.set_sided_piece(pos, piece.as_first());
+                }
+            }
+            board.set_first_hand(Piece(next(PIECE_TYPES as i32 - 1) as i8), 1);
+            board.set_second_hand(PIECE_PAWN, 1);
+            if !board.is_check() {
+                ret.push(board);
+            }
+        }
+        ret
+    }
+
+    #[test]
+    fn test_mate_in_1_matches_enumeration() {
+        let mate_in_1 = |board: &Board| {
+            board.enumerate_check().into_iter().find(|&mv| {
+                let mut board = board.clone();
+                board.apply_move(mv);
+                board.enumerate_check_avoidance().is_empty()
+            })
+        };
+        let mut mates = 0;
+        for board in random_boards(300) {
+            let expected = mate_in_1(&board);
+            assert_eq!(board.mate_in_1(), expected);
+            if expected.is_some() {
+                mates += 1;
+            }
+            let mate_in_3 = expected.or_else(|| {
+                board.enumerate_check().into_iter().find(|&mv| {
+                    let mut board = board.clone();
+                    board.apply_move(mv);
+                    board.enumerate_check_avoidance().iter().all(|&evasion| {
+                        let mut board = board.clone();
+                        board.apply_move(evasion);
+                        mate_in_1(&board).is_some()
+                    })
+                })
+            });
+            assert_eq!(board.mate_in_3(), mate_in_3);
+        }
+        assert!(mates > 0);
+    }
 }
@@ -0,0 +1,328 @@
+//! Reading and writing boards in the BOD format, e.g.
+//!
+//! ```text
+//! 後手の持駒：飛　角　金四　銀四　桂四　香四　歩十八
+//!   ９ ８ ７ ６ ５ ４ ３ ２ １
+//! +---------------------------+
+//! | ・ ・ ・ ・v玉 ・ ・ ・ ・|一
+//! | ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+//! ...
+//! +---------------------------+
+//! 先手の持駒：なし
+//! ```
+
+use crate::board::Board;
+use crate::common::*;
+use crate::notation::{kanji_number, parse_kanji_number, FILE_NAMES, RANK_NAMES};
+use crate::P;
+use std::fmt;
+use std::str::FromStr;
+
+/// Order of pieces in hand lines.
+pub(crate) const HAND_ORDER: [Piece; PIECE_TYPES - 1] = [
+    PIECE_ROOK,
+    PIECE_BISHOP,
+    PIECE_GOLD,
+    PIECE_SILVER,
+    PIECE_KNIGHT,
+    PIECE_LANCE,
+    PIECE_PAWN,
+];
+
+const FRAME: &str = "+---------------------------+";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseBodError {
+    /// The board row at the given line (0-indexed) is malformed.
+    InvalidRow(usize),
+    /// The hand at the given line (0-indexed) is malformed.
+    InvalidHand(usize),
+    /// The number of board rows is not 9.
+    WrongRowCount(usize),
+}
+
+impl fmt::Display for ParseBodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseBodError::InvalidRow(line) => write!(f, "invalid board row at line {}", line + 1),
+            ParseBodError::InvalidHand(line) => write!(f, "invalid hand at line {}", line + 1),
+            ParseBodError::WrongRowCount(n) => write!(f, "expected 9 board rows, found {}", n),
+        }
+    }
+}
+
+impl std::error::Error for ParseBodError {}
+
+fn write_hand(f: &mut fmt::Formatter, name: &str, hand: impl Fn(Piece) -> i8) -> fmt::Result {
+    write!(f, "{}の持駒：", name)?;
+    let mut empty = true;
+    for &piece in &HAND_ORDER {
+        let n = hand(piece);
+        if n == 0 {
+            continue;
+        }
+        if !empty {
+            write!(f, "　")?;
+        }
+        empty = false;
+        write!(f, "{}", piece.get_name())?;
+        if n > 1 {
+            write!(f, "{}", kanji_number(n as i32))?;
+        }
+    }
+    if empty {
+        write!(f, "なし")?;
+    }
+    writeln!(f)
+}
+
+impl Board {
+    /// Writes the board in the BOD format, including the hands.
+    /// `Display` prints only the grid of the board.
+    pub fn to_bod(&self) -> String {
+        Bod(self).to_string()
+    }
+}
+
+struct Bod<'a>(&'a Board);
+
+impl fmt::Display for Bod<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let board = self.0;
+        write_hand(f, "後手", |piece| board.get_second_hand(piece))?;
+        write!(f, " ")?;
+        for x in (0..BOARD_SIZE).rev() {
+            write!(f, " {}", FILE_NAMES[x as usize])?;
+        }
+        writeln!(f)?;
+        writeln!(f, "{}", FRAME)?;
+        for y in 0..BOARD_SIZE {
+            write!(f, "|")?;
+            for x in 0..BOARD_SIZE {
+                let piece = board.get_sided_piece(P(y, x));
+                if piece.is_empty() {
+                    write!(f, " ・")?;
+                } else if piece.is_first() {
+                    write!(f, " {}", piece.to_piece().get_name())?;
+                } else {
+                    write!(f, "v{}", piece.to_piece().get_name())?;
+                }
+            }
+            writeln!(f, "|{}", RANK_NAMES[y as usize])?;
+        }
+        writeln!(f, "{}", FRAME)?;
+        write_hand(f, "先手", |piece| board.get_first_hand(piece))
+    }
+}
+
+fn parse_piece_name(c: char) -> Option<Piece> {
+    let c = match c {
+        '竜' => '龍',
+        '王' => '玉',
+        _ => c,
+    };
+    PIECE_NAMES
+        .iter()
+        .position(|&n| n == c && n != ' ')
+        .map(|i| Piece(i as i8))
+}
+
+fn parse_hand(s: &str) -> Option<[i8; PIECE_TYPES]> {
+    let mut hand = [0; PIECE_TYPES];
+    let s = s.trim_matches(|c: char| c.is_whitespace());
+    if s == "なし" || s.is_empty() {
+        return Some(hand);
+    }
+    for item in s.split(|c: char| c.is_whitespace()) {
+        if item.is_empty() {
+            continue;
+        }
+        let mut chars = item.chars();
+        let piece = parse_piece_name(chars.next()?)?;
+        if piece.0 as usize >= PIECE_TYPES - 1 {
+            return None;
+        }
+        let rest = chars.as_str();
+        let n = if rest.is_empty() {
+            1
+        } else {
+            parse_kanji_number(rest)?
+        };
+        let total = PIECE_COUNTS[piece.0 as usize];
+        if !(1..=total as i32).contains(&n) {
+            return None;
+        }
+        hand[piece.0 as usize] = hand[piece.0 as usize]
+            .checked_add(n as i8)
+            .filter(|&n| n <= total)?;
+    }
+    Some(hand)
+}
+
+fn parse_row(s: &str) -> Option<[SidedPiece; BOARD_SIZE as usize]> {
+    let chars = s.chars().collect::<Vec<_>>();
+    if chars.len() < 1 + BOARD_SIZE as usize * 2 + 1 || chars[BOARD_SIZE as usize * 2 + 1] != '|' {
+        return None;
+    }
+    let mut row = [EMPTY_CELL; BOARD_SIZE as usize];
+    for x in 0..(BOARD_SIZE as usize) {
+        let side = chars[1 + x * 2];
+        let name = chars[2 + x * 2];
+        if name == '・' {
+            continue;
+        }
+        let piece = parse_piece_name(name)?;
+        row[x] = match side {
+            'v' => piece.as_second(),
+            ' ' | '+' | '^' => piece.as_first(),
+            _ => return None,
+        };
+    }
+    Some(row)
+}
+
+impl FromStr for Board {
+    type Err = ParseBodError;
+
+    /// Parses a board in the BOD format.
+    /// Lines other than hands and board rows (e.g. headers, frames and comments) are ignored.
+    fn from_str(s: &str) -> Result<Board, ParseBodError> {
+        let mut board = Board::new();
+        let mut rows = 0;
+
+        for (i, line) in s.lines().enumerate() {
+            let line = line.trim_end();
+            if let Some(hand) = line
+                .strip_prefix("後手の持駒：")
+                .or_else(|| line.strip_prefix("上手の持駒："))
+            {
+                let hand = parse_hand(hand).ok_or(ParseBodError::InvalidHand(i))?;
+                for (p, &count) in hand.iter().enumerate() {
+                    board.set_second_hand(Piece(p as i8), count);
+                }
+            } else if let Some(hand) = line
+                .strip_prefix("先手の持駒：")
+                .or_else(|| line.strip_prefix("下手の持駒："))
+            {
+                let hand = parse_hand(hand).ok_or(ParseBodError::InvalidHand(i))?;
+                for (p, &count) in hand.iter().enumerate() {
+                    board.set_first_hand(Piece(p as i8), count);
+                }
+            } else if line.starts_with('|') {
+                let row = parse_row(line).ok_or(ParseBodError::InvalidRow(i))?;
+                if rows < BOARD_SIZE {
+                    for x in 0..BOARD_SIZE {
+                        board.set_sided_piece(P(rows, x), row[x as usize]);
+                    }
+                }
+                rows += 1;
+            }
+        }
+        if rows != BOARD_SIZE {
+            return Err(ParseBodError::WrongRowCount(rows as usize));
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROBLEM: &str = "\
+後手の持駒：飛　角　金三　銀四　桂四　香四　歩十八
+  ９ ８ ７ ６ ５ ４ ３ ２ １
++---------------------------+
+| ・ ・ ・ ・v玉 ・ ・ ・ ・|一
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|二
+| ・ ・ ・ ・ 金 ・ ・ ・ ・|三
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|四
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|五
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|六
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|七
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|八
+| ・ ・ ・ ・ ・ ・ ・ ・ ・|九
++---------------------------+
+先手の持駒：飛　角
+";
+
+    #[test]
+    fn test_write_bod() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_GOLD.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+        for &(piece, n) in &[
+            (PIECE_ROOK, 1),
+            (PIECE_BISHOP, 1),
+            (PIECE_GOLD, 3),
+            (PIECE_SILVER, 4),
+            (PIECE_KNIGHT, 4),
+            (PIECE_LANCE, 4),
+            (PIECE_PAWN, 18),
+        ] {
+            board.set_second_hand(piece, n);
+        }
+        assert_eq!(board.to_bod(), PROBLEM);
+    }
+
+    #[test]
+    fn test_parse_bod() {
+        let board = PROBLEM.parse::<Board>().unwrap();
+        assert_eq!(board.get_sided_piece(P(0, 4)), PIECE_KING.as_second());
+        assert_eq!(board.get_sided_piece(P(2, 4)), PIECE_GOLD.as_first());
+        assert!(board.get_sided_piece(P(1, 4)).is_empty());
+        assert_eq!(board.get_first_hand(PIECE_ROOK), 1);
+        assert_eq!(board.get_first_hand(PIECE_GOLD), 0);
+        assert_eq!(board.get_second_hand(PIECE_GOLD), 3);
+        assert_eq!(board.get_second_hand(PIECE_PAWN), 18);
+
+        assert_eq!(
+            "|".parse::<Board>().err(),
+            Some(ParseBodError::InvalidRow(0))
+        );
+        assert_eq!(
+            PROBLEM.replace("金三", "金x").parse::<Board>().err(),
+            Some(ParseBodError::InvalidHand(0))
+        );
+        // more pieces than in a game
+        for hand in &["歩九十九", "歩十　歩九", "飛三"] {
+            assert_eq!(
+                PROBLEM.replace("飛　角　金三", hand).parse::<Board>().err(),
+                Some(ParseBodError::InvalidHand(0))
+            );
+        }
+        assert_eq!(
+            PROBLEM
+                .replace("| ・ ・ ・ ・ ・ ・ ・ ・ ・|九\n", "")
+                .parse::<Board>()
+                .err(),
+            Some(ParseBodError::WrongRowCount(8))
+        );
+    }
+
+    #[test]
+    fn test_bod_roundtrip() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_LANCE.as_second());
+        board.set_sided_piece(P(1, 2), PIECE_KING.as_second());
+        board.set_sided_piece(P(3, 5), PIECE_PROMOTED_ROOK.as_first());
+        board.set_sided_piece(P(4, 4), PIECE_PROMOTED_BISHOP.as_second());
+        board.set_sided_piece(P(8, 8), PIECE_KNIGHT.promote().as_first());
+        board.set_sided_piece(P(6, 3), PIECE_PAWN.promote().as_second());
+        board.set_first_hand(PIECE_PAWN, 2);
+        board.set_second_hand(PIECE_SILVER, 1);
+
+        let parsed = board.to_bod().parse::<Board>().unwrap();
+        assert_eq!(parsed.to_bod(), board.to_bod());
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                assert_eq!(
+                    parsed.get_sided_piece(P(y, x)),
+                    board.get_sided_piece(P(y, x))
+                );
+            }
+        }
+    }
+}
@@ -5,6 +5,9 @@ pub const BOARD_CELLS: i32 = BOARD_SIZE * BOARD_SIZE;
 
 pub const PIECE_TYPES: usize = 8;
 
+/// Number of pieces of each kind except kings in a game, indexed by `Piece`.
+pub const PIECE_COUNTS: [i8; PIECE_TYPES - 1] = [18, 4, 4, 4, 4, 2, 2];
+
 /// There are 8 kinds of pieces, 6 of which can promote.
 /// Non-promoted pieces are numbered by 0 through 7.
 /// The promoted piece of piece i is represented by i+8.
@@ -22,10 +25,10 @@ pub const PIECE_NAMES: [char; PIECE_TYPES * 2] = [
     '杏', // promoted lance
     '圭', // promoted knight
     '全', // promoted silver
-    ' ',   // gold has no promotion
+    ' ',  // gold has no promotion
     '馬', // promoted bishop
     '龍', // promoted rook
-    ' ',   // king has no promotion
+    ' ',  // king has no promotion
 ];
 
 pub const PIECE_MOVES_COUNT: usize = 8;
@@ -237,6 +240,22 @@ pub enum Move {
     FromHand(P, SidedPiece),
 }
 
+impl Move {
+    /// Returns the cell the piece is moved (or dropped) to.
+    pub fn dest(self) -> P {
+        match self {
+            Move::NoPromotion(_, dest) | Move::Promotion(_, dest) | Move::FromHand(dest, _) => dest,
+        }
+    }
+    /// Returns the cell the piece is moved from, or `None` for drops.
+    pub fn src(self) -> Option<P> {
+        match self {
+            Move::NoPromotion(src, _) | Move::Promotion(src, _) => Some(src),
+            Move::FromHand(_, _) => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Eq, PartialEq, Debug)]
 pub struct SidedPiece(pub i8);
 
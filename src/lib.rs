@@ -1,6 +1,12 @@
 pub mod board;
+pub mod bod;
 pub mod common;
+pub mod notation;
 mod pos;
+#[cfg(feature = "serde")]
+mod serde_impls;
 pub mod solver;
+pub mod svg;
+pub mod usi;
 
 pub use pos::*;
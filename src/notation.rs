@@ -0,0 +1,478 @@
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+use std::fmt;
+
+/// Names of files (columns) in Japanese notation, indexed by `file - 1`.
+pub const FILE_NAMES: [char; BOARD_SIZE as usize] =
+    ['１', '２', '３', '４', '５', '６', '７', '８', '９'];
+
+/// Names of ranks (rows) in Japanese notation, indexed by `rank - 1`.
+pub const RANK_NAMES: [char; BOARD_SIZE as usize] =
+    ['一', '二', '三', '四', '五', '六', '七', '八', '九'];
+
+/// Names of pieces as written in move notation.
+/// Unlike `PIECE_NAMES`, promoted lance, knight and silver use two characters.
+pub const PIECE_NOTATION_NAMES: [&str; PIECE_TYPES * 2] = [
+    "歩", "香", "桂", "銀", "金", "角", "飛", "玉", "と", "成香", "成桂", "成銀", "", "馬", "龍",
+    "",
+];
+
+/// Returns the file number (1 through 9) of `pos` in the common Shogi notation.
+pub fn file_of(pos: P) -> i32 {
+    BOARD_SIZE - pos.x()
+}
+
+/// Returns the rank number (1 through 9) of `pos` in the common Shogi notation.
+pub fn rank_of(pos: P) -> i32 {
+    pos.y() + 1
+}
+
+/// Returns the name of `pos` such as `２三`.
+pub fn square_name(pos: P) -> String {
+    format!(
+        "{}{}",
+        FILE_NAMES[(file_of(pos) - 1) as usize],
+        RANK_NAMES[(rank_of(pos) - 1) as usize]
+    )
+}
+
+/// Returns `n` (1 through 99) written in kanji numerals, e.g. `十八` for 18.
+pub fn kanji_number(n: i32) -> String {
+    assert!((1..100).contains(&n));
+    let mut ret = String::new();
+    if n >= 20 {
+        ret.push(RANK_NAMES[(n / 10 - 1) as usize]);
+    }
+    if n >= 10 {
+        ret.push('十');
+    }
+    if n % 10 != 0 {
+        ret.push(RANK_NAMES[(n % 10 - 1) as usize]);
+    }
+    ret
+}
+
+/// Parses kanji numerals written by `kanji_number`.
+pub fn parse_kanji_number(s: &str) -> Option<i32> {
+    let digit = |c: char| {
+        RANK_NAMES
+            .iter()
+            .position(|&d| d == c)
+            .map(|i| i as i32 + 1)
+    };
+    let chars = s.chars().collect::<Vec<_>>();
+    match chars.iter().position(|&c| c == '十') {
+        None if chars.len() == 1 => digit(chars[0]),
+        None => None,
+        Some(i) => {
+            let tens = match i {
+                0 => 1,
+                1 => digit(chars[0])?,
+                _ => return None,
+            };
+            let ones = match chars.len() - i - 1 {
+                0 => 0,
+                1 => digit(chars[i + 1])?,
+                _ => return None,
+            };
+            Some(tens * 10 + ones)
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseMoveError {
+    /// No move of the player matches the notation.
+    NoMatch,
+    /// More than one move matches the notation.
+    Ambiguous,
+}
+
+impl fmt::Display for ParseMoveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseMoveError::NoMatch => write!(f, "no move matches the notation"),
+            ParseMoveError::Ambiguous => write!(f, "the notation matches more than one move"),
+        }
+    }
+}
+
+impl std::error::Error for ParseMoveError {}
+
+/// Formats `mv` played on `board` in the Japanese notation, e.g. `２三金打`, `同　桂成` or `４二銀右上`.
+/// `prev` is the move played just before `mv`, which is used for `同`.
+/// `打` is always written for drops.
+pub fn format_move(board: &Board, mv: Move, prev: Option<Move>) -> String {
+    let dest = mv.dest();
+    let mut ret = match prev {
+        Some(prev) if prev.dest() == dest => String::from("同　"),
+        _ => square_name(dest),
+    };
+    match mv {
+        Move::NoPromotion(src, _) | Move::Promotion(src, _) => {
+            let piece = board.get_sided_piece(src);
+            let is_second = piece.is_second();
+            ret.push_str(PIECE_NOTATION_NAMES[piece.to_piece().0 as usize]);
+            ret.push_str(&relative_position(board, src, dest));
+            if let Move::Promotion(_, _) = mv {
+                ret.push('成');
+            } else if piece.to_piece().has_promotion()
+                && (Board::is_promotion_zone(src, is_second)
+                    || Board::is_promotion_zone(dest, is_second))
+            {
+                ret.push_str("不成");
+            }
+        }
+        Move::FromHand(_, piece) => {
+            ret.push_str(PIECE_NOTATION_NAMES[piece.to_piece().0 as usize]);
+            ret.push('打');
+        }
+    }
+    ret
+}
+
+/// Computes the disambiguation suffix (`右`, `左`, `直`, `上`, `引`, `寄` or their combinations)
+/// for moving the piece at `src` to `dest`.
+fn relative_position(board: &Board, src: P, dest: P) -> String {
+    let piece = board.get_sided_piece(src);
+    let is_second = piece.is_second();
+
+    let mut candidates = vec![src];
+    for y in 0..BOARD_SIZE {
+        for x in 0..BOARD_SIZE {
+            let pos = P(y, x);
+            if pos != src
+                && board.get_sided_piece(pos) == piece
+                && board
+                    .enumerate_moves(pos)
+                    .iter()
+                    .any(|mv| mv.dest() == dest)
+            {
+                candidates.push(pos);
+            }
+        }
+    }
+    if candidates.len() == 1 {
+        return String::new();
+    }
+
+    // Both are measured from the view of the player who moves the piece:
+    // `forward` is positive for moves toward the opponent and `right` is larger for pieces on the right.
+    let forward = |pos: P| {
+        if is_second {
+            dest.y() - pos.y()
+        } else {
+            pos.y() - dest.y()
+        }
+    };
+    let right = |pos: P| if is_second { -pos.x() } else { pos.x() };
+    let vertical = |pos: P| {
+        let f = forward(pos);
+        if f > 0 {
+            '上'
+        } else if f < 0 {
+            '引'
+        } else {
+            '寄'
+        }
+    };
+    let uses_straight = {
+        let p = piece.to_piece();
+        p != PIECE_PROMOTED_BISHOP && p != PIECE_PROMOTED_ROOK
+    };
+    let horizontal = |group: &[P]| {
+        let is_straight = |pos: P| uses_straight && pos.x() == dest.x() && forward(pos) > 0;
+        if is_straight(src) && group.iter().all(|&pos| pos == src || !is_straight(pos)) {
+            Some('直')
+        } else if group
+            .iter()
+            .all(|&pos| pos == src || right(pos) < right(src))
+        {
+            Some('右')
+        } else if group
+            .iter()
+            .all(|&pos| pos == src || right(pos) > right(src))
+        {
+            Some('左')
+        } else {
+            None
+        }
+    };
+
+    let same_vertical = candidates
+        .iter()
+        .cloned()
+        .filter(|&pos| vertical(pos) == vertical(src))
+        .collect::<Vec<_>>();
+    if same_vertical.len() == 1 {
+        return vertical(src).to_string();
+    }
+    if let Some(h) = horizontal(&candidates) {
+        return h.to_string();
+    }
+    let mut ret = String::new();
+    if let Some(h) = horizontal(&same_vertical) {
+        ret.push(h);
+    }
+    ret.push(vertical(src));
+    ret
+}
+
+/// Parses a move written in the Japanese notation against `board`.
+/// `is_second` tells which player moves, and `prev` is the previous move referred to by `同`.
+/// A leading `▲`/`△`/`☗`/`☖`, ASCII digits and the alternative piece names
+/// (`竜`, `王`, `杏`, `圭`, `全`) are accepted.
+/// `打` and the relative position (`右`, `上` and so on) may be omitted or redundant
+/// as long as exactly one move matches.
+pub fn parse_move(
+    board: &Board,
+    s: &str,
+    is_second: bool,
+    prev: Option<Move>,
+) -> Result<Move, ParseMoveError> {
+    let normalized = normalize(s);
+    let candidates = board.enumerate_player_moves(is_second);
+
+    let mut matches = candidates
+        .iter()
+        .cloned()
+        .filter(|&mv| format_move(board, mv, prev) == normalized)
+        .collect::<Vec<_>>();
+    if matches.is_empty() {
+        let with_drop = normalized.clone() + "打";
+        matches = candidates
+            .iter()
+            .cloned()
+            .filter(|&mv| format_move(board, mv, prev) == with_drop)
+            .collect::<Vec<_>>();
+    }
+    if matches.is_empty() {
+        let stripped = strip_relative_position(&normalized);
+        let with_drop = stripped.clone() + "打";
+        matches = candidates
+            .iter()
+            .cloned()
+            .filter(|&mv| {
+                let s = strip_relative_position(&format_move(board, mv, prev));
+                s == stripped || s == with_drop
+            })
+            .collect::<Vec<_>>();
+    }
+    match matches.len() {
+        0 => Err(ParseMoveError::NoMatch),
+        1 => Ok(matches[0]),
+        _ => Err(ParseMoveError::Ambiguous),
+    }
+}
+
+fn normalize(s: &str) -> String {
+    let s = s.trim().trim_start_matches(['▲', '△', '☗', '☖']).trim();
+    let mut chars = s.chars().collect::<Vec<_>>();
+
+    if chars.len() >= 2 {
+        if let Some(d) = chars[0].to_digit(10) {
+            if (1..=9).contains(&d) {
+                chars[0] = FILE_NAMES[d as usize - 1];
+            }
+        }
+        let rank = match chars[1] {
+            c @ '1'..='9' => Some(c as u32 - '0' as u32),
+            c @ '１'..='９' => Some(c as u32 - '１' as u32 + 1),
+            _ => None,
+        };
+        if let Some(r) = rank {
+            chars[1] = RANK_NAMES[r as usize - 1];
+        }
+    }
+
+    let mut ret = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '同' => {
+                ret.push_str("同　");
+                while i + 1 < chars.len() && chars[i + 1].is_whitespace() {
+                    i += 1;
+                }
+            }
+            '竜' => ret.push('龍'),
+            '王' => ret.push('玉'),
+            '杏' => ret.push_str("成香"),
+            '圭' => ret.push_str("成桂"),
+            '全' => ret.push_str("成銀"),
+            _ if c.is_whitespace() => (),
+            _ => ret.push(c),
+        }
+        i += 1;
+    }
+    ret
+}
+
+fn strip_relative_position(s: &str) -> String {
+    s.chars().filter(|c| !"右左直上引寄".contains(*c)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kanji_number() {
+        for n in 1..100 {
+            assert_eq!(parse_kanji_number(&kanji_number(n)), Some(n));
+        }
+        assert_eq!(kanji_number(4), "四");
+        assert_eq!(kanji_number(18), "十八");
+        assert_eq!(parse_kanji_number(""), None);
+        assert_eq!(parse_kanji_number("十十"), None);
+    }
+
+    #[test]
+    fn test_format_move() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(4, 7), PIECE_KNIGHT.as_first());
+        board.set_sided_piece(P(1, 3), PIECE_GOLD.as_second());
+        board.set_first_hand(PIECE_GOLD, 1);
+
+        let drop = Move::FromHand(P(2, 7), PIECE_GOLD.as_first());
+        assert_eq!(format_move(&board, drop, None), "２三金打");
+
+        let prev = Move::NoPromotion(P(1, 3), P(2, 6));
+        let mv = Move::Promotion(P(4, 7), P(2, 6));
+        assert_eq!(format_move(&board, mv, Some(prev)), "同　桂成");
+        let mv = Move::NoPromotion(P(4, 7), P(2, 6));
+        assert_eq!(format_move(&board, mv, None), "３三桂不成");
+
+        let mv = Move::NoPromotion(P(1, 3), P(1, 2));
+        assert_eq!(format_move(&board, mv, None), "７二金");
+    }
+
+    #[test]
+    fn test_format_move_relative_position() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(2, 6), PIECE_SILVER.as_first());
+        board.set_sided_piece(P(0, 6), PIECE_SILVER.as_first());
+        board.set_sided_piece(P(2, 4), PIECE_SILVER.as_first());
+
+        let mv = Move::NoPromotion(P(2, 6), P(1, 5));
+        assert_eq!(format_move(&board, mv, None), "４二銀右上不成");
+        let mv = Move::NoPromotion(P(2, 4), P(1, 5));
+        assert_eq!(format_move(&board, mv, None), "４二銀左不成");
+        let mv = Move::Promotion(P(0, 6), P(1, 5));
+        assert_eq!(format_move(&board, mv, None), "４二銀引成");
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(5, 3), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(5, 4), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(5, 5), PIECE_GOLD.as_first());
+        assert_eq!(
+            format_move(&board, Move::NoPromotion(P(5, 3), P(4, 4)), None),
+            "５五金左"
+        );
+        assert_eq!(
+            format_move(&board, Move::NoPromotion(P(5, 4), P(4, 4)), None),
+            "５五金直"
+        );
+        assert_eq!(
+            format_move(&board, Move::NoPromotion(P(5, 5), P(4, 4)), None),
+            "５五金右"
+        );
+
+        // left and right are seen from the second player
+        let mut board = Board::new();
+        board.set_sided_piece(P(3, 3), PIECE_GOLD.as_second());
+        board.set_sided_piece(P(3, 5), PIECE_GOLD.as_second());
+        assert_eq!(
+            format_move(&board, Move::NoPromotion(P(3, 3), P(4, 4)), None),
+            "５五金右"
+        );
+        assert_eq!(
+            format_move(&board, Move::NoPromotion(P(3, 5), P(4, 4)), None),
+            "５五金左"
+        );
+    }
+
+    #[test]
+    fn test_parse_move() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(4, 7), PIECE_KNIGHT.as_first());
+        board.set_sided_piece(P(2, 6), PIECE_SILVER.as_first());
+        board.set_sided_piece(P(2, 4), PIECE_SILVER.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+        board.set_second_hand(PIECE_PAWN, 1);
+
+        assert_eq!(
+            parse_move(&board, "▲２三金打", false, None),
+            Ok(Move::FromHand(P(2, 7), PIECE_GOLD.as_first()))
+        );
+        assert_eq!(
+            parse_move(&board, "23金", false, None),
+            Ok(Move::FromHand(P(2, 7), PIECE_GOLD.as_first()))
+        );
+        assert_eq!(
+            parse_move(
+                &board,
+                "同 桂成",
+                false,
+                Some(Move::NoPromotion(P(0, 0), P(2, 6)))
+            ),
+            Err(ParseMoveError::NoMatch)
+        );
+        assert_eq!(
+            parse_move(
+                &board,
+                "同 桂成",
+                false,
+                Some(Move::NoPromotion(P(0, 0), P(2, 8)))
+            ),
+            Ok(Move::Promotion(P(4, 7), P(2, 8)))
+        );
+        assert_eq!(
+            parse_move(&board, "４二銀右成", false, None),
+            Ok(Move::Promotion(P(2, 6), P(1, 5)))
+        );
+        assert_eq!(
+            parse_move(&board, "４二銀成", false, None),
+            Err(ParseMoveError::Ambiguous)
+        );
+        assert_eq!(
+            parse_move(&board, "１三桂右成", false, None),
+            Ok(Move::Promotion(P(4, 7), P(2, 8)))
+        );
+        assert_eq!(
+            parse_move(&board, "△５二歩打", true, None),
+            Ok(Move::FromHand(P(1, 4), PIECE_PAWN.as_second()))
+        );
+
+        // a second pawn on the file (nifu)
+        board.set_sided_piece(P(6, 4), PIECE_PAWN.as_second());
+        assert_eq!(
+            parse_move(&board, "△５二歩打", true, None),
+            Err(ParseMoveError::NoMatch)
+        );
+        assert_eq!(
+            parse_move(&board, "△６二歩", true, None),
+            Ok(Move::FromHand(P(1, 3), PIECE_PAWN.as_second()))
+        );
+    }
+
+    #[test]
+    fn test_format_parse_roundtrip() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 3), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(5, 1), PIECE_PROMOTED_BISHOP.as_first());
+        board.set_sided_piece(P(3, 7), PIECE_LANCE.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+
+        for &mv in &board.enumerate_first_moves() {
+            let s = format_move(&board, mv, None);
+            assert_eq!(parse_move(&board, &s, false, None), Ok(mv), "{}", s);
+        }
+    }
+}
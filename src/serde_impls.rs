@@ -0,0 +1,402 @@
+//! `serde` support, enabled by the `serde` feature.
+//!
+//! The schema is meant to be read by humans:
+//! - cells (`P`) are strings of the file and the rank in the common Shogi notation, e.g. `"76"`;
+//! - pieces (`Piece`) are names such as `"gold"` or `"promoted_rook"`;
+//! - sided pieces are objects like `{"side": "second", "piece": "king"}`;
+//! - moves are objects like `{"type": "move", "from": "77", "to": "76", "promote": false}`
+//!   or `{"type": "drop", "to": "55", "side": "first", "piece": "pawn"}`;
+//! - boards are objects with `pieces` (a map from cells to sided pieces)
+//!   and `hand_first` / `hand_second` (maps from pieces to counts, omitting zeros).
+//! - the results and options of the solver use snake_case names, and enums with data are
+//!   tagged by `type` (`reason` for `InvalidPosition`, which is nested in `SolveResult`),
+//!   e.g. `{"type": "unknown", "reason": "max_depth"}`.
+
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+use serde::de::{self, Deserializer};
+use serde::ser::{self, Serializer};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const PIECE_SERDE_NAMES: [&str; PIECE_TYPES * 2] = [
+    "pawn",
+    "lance",
+    "knight",
+    "silver",
+    "gold",
+    "bishop",
+    "rook",
+    "king",
+    "promoted_pawn",
+    "promoted_lance",
+    "promoted_knight",
+    "promoted_silver",
+    "",
+    "promoted_bishop",
+    "promoted_rook",
+    "",
+];
+
+impl Serialize for P {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !(0 <= self.y() && self.y() < BOARD_SIZE && 0 <= self.x() && self.x() < BOARD_SIZE) {
+            return Err(ser::Error::custom("cell out of the board"));
+        }
+        serializer.serialize_str(&format!("{}{}", BOARD_SIZE - self.x(), self.y() + 1))
+    }
+}
+
+impl<'de> Deserialize<'de> for P {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<P, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let digits = s.chars().map(|c| c.to_digit(10)).collect::<Vec<_>>();
+        match digits[..] {
+            [Some(file), Some(rank)] if file >= 1 && rank >= 1 => {
+                Ok(P(rank as i32 - 1, BOARD_SIZE - file as i32))
+            }
+            _ => Err(de::Error::invalid_value(
+                de::Unexpected::Str(&s),
+                &"a cell such as \"76\"",
+            )),
+        }
+    }
+}
+
+impl Serialize for Piece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match PIECE_SERDE_NAMES.get(self.0 as usize) {
+            Some(name) if !name.is_empty() => serializer.serialize_str(name),
+            _ => Err(ser::Error::custom("invalid piece")),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Piece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Piece, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        PIECE_SERDE_NAMES
+            .iter()
+            .position(|&name| !name.is_empty() && name == s)
+            .map(|i| Piece(i as i8))
+            .ok_or_else(|| de::Error::unknown_variant(&s, &PIECE_SERDE_NAMES))
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+enum Side {
+    First,
+    Second,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SidedPieceRepr {
+    side: Side,
+    piece: Piece,
+}
+
+impl SidedPieceRepr {
+    fn new(piece: SidedPiece) -> Option<SidedPieceRepr> {
+        if piece.is_empty() {
+            return None;
+        }
+        let side = if piece.is_first() {
+            Side::First
+        } else {
+            Side::Second
+        };
+        Some(SidedPieceRepr {
+            side,
+            piece: piece.to_piece(),
+        })
+    }
+    fn to_sided_piece(&self) -> SidedPiece {
+        match self.side {
+            Side::First => self.piece.as_first(),
+            Side::Second => self.piece.as_second(),
+        }
+    }
+}
+
+impl Serialize for SidedPiece {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SidedPieceRepr::new(*self)
+            .ok_or_else(|| ser::Error::custom("empty cell can't be serialized"))?
+            .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SidedPiece {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SidedPiece, D::Error> {
+        Ok(SidedPieceRepr::deserialize(deserializer)?.to_sided_piece())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum MoveRepr {
+    Move { from: P, to: P, promote: bool },
+    Drop { to: P, side: Side, piece: Piece },
+}
+
+impl Serialize for Move {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let repr = match *self {
+            Move::NoPromotion(from, to) => MoveRepr::Move {
+                from,
+                to,
+                promote: false,
+            },
+            Move::Promotion(from, to) => MoveRepr::Move {
+                from,
+                to,
+                promote: true,
+            },
+            Move::FromHand(to, piece) => {
+                let piece = SidedPieceRepr::new(piece)
+                    .ok_or_else(|| ser::Error::custom("empty cell can't be dropped"))?;
+                MoveRepr::Drop {
+                    to,
+                    side: piece.side,
+                    piece: piece.piece,
+                }
+            }
+        };
+        repr.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Move {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Move, D::Error> {
+        Ok(match MoveRepr::deserialize(deserializer)? {
+            MoveRepr::Move {
+                from,
+                to,
+                promote: false,
+            } => Move::NoPromotion(from, to),
+            MoveRepr::Move {
+                from,
+                to,
+                promote: true,
+            } => Move::Promotion(from, to),
+            MoveRepr::Drop { to, side, piece } => {
+                Move::FromHand(to, SidedPieceRepr { side, piece }.to_sided_piece())
+            }
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct BoardRepr {
+    pieces: BTreeMap<String, SidedPiece>,
+    hand_first: BTreeMap<String, i8>,
+    hand_second: BTreeMap<String, i8>,
+}
+
+impl Serialize for Board {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut pieces = BTreeMap::new();
+        for y in 0..BOARD_SIZE {
+            for x in 0..BOARD_SIZE {
+                let piece = self.get_sided_piece(P(y, x));
+                if !piece.is_empty() {
+                    pieces.insert(format!("{}{}", BOARD_SIZE - x, y + 1), piece);
+                }
+            }
+        }
+        let hand = |get: &dyn Fn(Piece) -> i8| {
+            (0..(PIECE_TYPES - 1))
+                .filter(|&i| get(Piece(i as i8)) != 0)
+                .map(|i| (PIECE_SERDE_NAMES[i].to_string(), get(Piece(i as i8))))
+                .collect::<BTreeMap<_, _>>()
+        };
+        BoardRepr {
+            pieces,
+            hand_first: hand(&|piece| self.get_first_hand(piece)),
+            hand_second: hand(&|piece| self.get_second_hand(piece)),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Board {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Board, D::Error> {
+        let repr = BoardRepr::deserialize(deserializer)?;
+        let mut board = Board::new();
+        for (pos, &piece) in &repr.pieces {
+            let pos = P::deserialize(de::value::StrDeserializer::<D::Error>::new(pos))?;
+            if !board.is_inside_board(pos) {
+                return Err(de::Error::custom(format!(
+                    "cell {:?} out of the board",
+                    pos
+                )));
+            }
+            board.set_sided_piece(pos, piece);
+        }
+        let hand_piece = |name: &str, n: i8| -> Result<Piece, D::Error> {
+            let piece = Piece::deserialize(de::value::StrDeserializer::<D::Error>::new(name))?;
+            if piece.0 as usize >= PIECE_TYPES - 1 {
+                return Err(de::Error::custom(format!("{} can't be in hand", name)));
+            }
+            if !(0..=PIECE_COUNTS[piece.0 as usize]).contains(&n) {
+                return Err(de::Error::custom(format!(
+                    "{} {} can't be in hand",
+                    n, name
+                )));
+            }
+            Ok(piece)
+        };
+        for (name, &n) in &repr.hand_first {
+            board.set_first_hand(hand_piece(name, n)?, n);
+        }
+        for (name, &n) in &repr.hand_second {
+            board.set_second_hand(hand_piece(name, n)?, n);
+        }
+        Ok(board)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{
+        solve, solve_proof_tree, solve_with_options, DefenderHand, InvalidPosition, MudaaiRule,
+        ProofTree, SolveOptions, SolveReport, SolveResult, StopReason,
+    };
+
+    #[test]
+    fn test_serde_cell_and_piece() {
+        assert_eq!(serde_json::to_string(&P(5, 2)).unwrap(), "\"76\"");
+        assert_eq!(serde_json::from_str::<P>("\"76\"").unwrap(), P(5, 2));
+        assert!(serde_json::from_str::<P>("\"7\"").is_err());
+
+        assert_eq!(
+            serde_json::to_string(&PIECE_PROMOTED_ROOK).unwrap(),
+            "\"promoted_rook\""
+        );
+        assert_eq!(
+            serde_json::from_str::<Piece>("\"gold\"").unwrap(),
+            PIECE_GOLD
+        );
+        assert!(serde_json::to_string(&Piece(12)).is_err());
+        assert!(serde_json::from_str::<Piece>("\"queen\"").is_err());
+
+        assert_eq!(
+            serde_json::to_string(&PIECE_KING.as_second()).unwrap(),
+            r#"{"side":"second","piece":"king"}"#
+        );
+        assert!(serde_json::to_string(&EMPTY_CELL).is_err());
+    }
+
+    #[test]
+    fn test_serde_move() {
+        let moves = vec![
+            Move::NoPromotion(P(6, 2), P(5, 2)),
+            Move::Promotion(P(7, 1), P(1, 7)),
+            Move::FromHand(P(4, 4), PIECE_PAWN.as_first()),
+            Move::FromHand(P(1, 4), PIECE_GOLD.as_second()),
+        ];
+        let json = serde_json::to_string(&moves).unwrap();
+        assert!(json.starts_with(r#"[{"type":"move","from":"77","to":"76","promote":false}"#));
+        assert!(json.ends_with(r#"{"type":"drop","to":"52","side":"second","piece":"gold"}]"#));
+        assert_eq!(serde_json::from_str::<Vec<Move>>(&json).unwrap(), moves);
+    }
+
+    #[test]
+    fn test_serde_board() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_PROMOTED_BISHOP.as_first());
+        board.set_first_hand(PIECE_GOLD, 2);
+        board.set_second_hand(PIECE_PAWN, 18);
+
+        let json = serde_json::to_string(&board).unwrap();
+        assert_eq!(
+            json,
+            concat!(
+                r#"{"pieces":{"51":{"side":"second","piece":"king"},"#,
+                r#""53":{"side":"first","piece":"promoted_bishop"}},"#,
+                r#""hand_first":{"gold":2},"hand_second":{"pawn":18}}"#
+            )
+        );
+        let parsed = serde_json::from_str::<Board>(&json).unwrap();
+        assert_eq!(parsed.to_bod(), board.to_bod());
+
+        assert!(serde_json::from_str::<Board>(
+            r#"{"pieces":{},"hand_first":{"king":1},"hand_second":{}}"#
+        )
+        .is_err());
+        for hand in &[r#"{"pawn":-1}"#, r#"{"pawn":19}"#, r#"{"rook":3}"#] {
+            let json = format!(
+                r#"{{"pieces":{{}},"hand_first":{{}},"hand_second":{}}}"#,
+                hand
+            );
+            assert!(serde_json::from_str::<Board>(&json).is_err());
+        }
+    }
+
+    #[test]
+    fn test_serde_solution() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+
+        let sol = solve(&board, 3);
+        let json = serde_json::to_string(&sol).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Option<Vec<Move>>>(&json).unwrap(),
+            sol
+        );
+
+        let report = solve_with_options(&board, &SolveOptions::default());
+        let json = serde_json::to_string(&report).unwrap();
+        assert!(json.starts_with(r#"{"result":{"type":"mate","moves":[{"type":"move","#));
+        assert_eq!(serde_json::from_str::<SolveReport>(&json).unwrap(), report);
+
+        let results = [
+            (
+                SolveResult::Unknown {
+                    reason: StopReason::MaxDepth,
+                },
+                r#"{"type":"unknown","reason":"max_depth"}"#,
+            ),
+            (
+                SolveResult::InvalidPosition(InvalidPosition::TooManyPieces(PIECE_GOLD)),
+                r#"{"type":"invalid_position","reason":"too_many_pieces","piece":"gold"}"#,
+            ),
+            (
+                SolveResult::InvalidPosition(InvalidPosition::NoDefenderKing),
+                r#"{"type":"invalid_position","reason":"no_defender_king"}"#,
+            ),
+        ];
+        for (result, expected) in &results {
+            let json = serde_json::to_string(result).unwrap();
+            assert_eq!(json, *expected);
+            assert_eq!(serde_json::from_str::<SolveResult>(&json).unwrap(), *result);
+        }
+
+        let hands = [
+            (DefenderHand::AsGiven, r#"{"type":"as_given"}"#),
+            (
+                DefenderHand::Explicit([1, 0, 0, 0, 2, 0, 0]),
+                r#"{"type":"explicit","counts":[1,0,0,0,2,0,0]}"#,
+            ),
+        ];
+        for (hand, expected) in &hands {
+            let json = serde_json::to_string(hand).unwrap();
+            assert_eq!(json, *expected);
+            assert_eq!(serde_json::from_str::<DefenderHand>(&json).unwrap(), *hand);
+        }
+        assert_eq!(
+            serde_json::to_string(&MudaaiRule::Unprotected).unwrap(),
+            r#""unprotected""#
+        );
+
+        let tree = solve_proof_tree(&board, &SolveOptions::default()).unwrap();
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(serde_json::from_str::<ProofTree>(&json).unwrap(), tree);
+    }
+}
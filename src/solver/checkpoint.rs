@@ -0,0 +1,655 @@
+//! Checkpoints of long searches, which save the transposition table, the depth of the current
+//! iteration and the options to a file, so that the search can be resumed after a crash.
+//!
+//! The entries in the table hold for any depth limit, so a resumed search restarts the
+//! iteration where it stopped and finds the same result as an uninterrupted one.
+//!
+//! The file is a little-endian binary of the header (magic, version, hash of the position,
+//! depth, nodes), the options, and the entries of the table.
+
+use super::hand::Hands;
+use super::mudaai::MudaaiRule;
+use super::options::{CheckpointOptions, DefenderHand};
+use super::ordering::{EvasionOrder, MoveOrdering};
+use super::{solve_from, with_defender_hand, Line, MinimaxEntry, SolveOptions, SolveReport};
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+use std::convert::TryInto;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const MAGIC: &[u8; 4] = b"TSCP";
+const VERSION: u32 = 3;
+
+/// Largest memory of the transposition table accepted from a file, so that a corrupted
+/// checkpoint can't make the resumed search allocate without bound.
+const MAX_TT_MEMORY: usize = 1 << 30;
+
+/// Saved state of a search by `solve_with_options`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    /// Hash of the position searched, with the second player's hand.
+    position: u64,
+    /// Depth of the iteration to resume.
+    pub depth: i32,
+    /// Number of nodes searched before the checkpoint.
+    pub nodes: u64,
+    /// Options of the search. The deadline, the cancel handle and the progress observer
+    /// are not saved, so they are `None`. The memory of the table is capped at 1 GiB when
+    /// loaded.
+    pub options: SolveOptions,
+    entries: Vec<(u64, MinimaxEntry)>,
+}
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    /// The file is not a checkpoint, or it is corrupted.
+    Malformed,
+    /// The checkpoint is of another position, or of another hand of the second player.
+    PositionMismatch,
+    /// The checkpoint is of a search with another rule of futile interpositions,
+    /// so the saved results don't hold.
+    RuleMismatch,
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "{}", err),
+            CheckpointError::Malformed => write!(f, "malformed checkpoint"),
+            CheckpointError::PositionMismatch => write!(f, "checkpoint of another position"),
+            CheckpointError::RuleMismatch => {
+                write!(f, "checkpoint with another rule of futile interpositions")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> CheckpointError {
+        CheckpointError::Io(err)
+    }
+}
+
+impl Checkpoint {
+    pub(super) fn new(
+        position: u64,
+        depth: i32,
+        nodes: u64,
+        options: &SolveOptions,
+        entries: Vec<(u64, MinimaxEntry)>,
+    ) -> Checkpoint {
+        Checkpoint {
+            position,
+            depth,
+            nodes,
+            options: SolveOptions {
+                deadline: None,
+                cancel: None,
+                progress: None,
+                ..options.clone()
+            },
+            entries,
+        }
+    }
+
+    /// Number of entries of the transposition table saved.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(super) fn entries(&self) -> &[(u64, MinimaxEntry)] {
+        &self.entries
+    }
+
+    pub fn load(path: &Path) -> Result<Checkpoint, CheckpointError> {
+        let data = fs::read(path)?;
+        let mut reader = Reader { data: &data };
+        let ret = reader.checkpoint().ok_or(CheckpointError::Malformed)?;
+        if !reader.data.is_empty() {
+            return Err(CheckpointError::Malformed);
+        }
+        Ok(ret)
+    }
+
+    /// Saves the checkpoint to `path`. The file is written next to it first and then renamed,
+    /// so `path` is never left half written. Fails if the path of the checkpoint options
+    /// is not valid UTF-8.
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let mut writer = Writer { data: vec![] };
+        writer.checkpoint(self)?;
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, &writer.data)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// Resumes the search of `board` saved in `checkpoint`, with `options`.
+///
+/// `checkpoint.options` can be used to continue with the same options, but e.g. the limit
+/// of nodes which stopped the search should be raised. The nodes before the checkpoint
+/// count towards `max_nodes` and the statistics.
+pub fn resume(
+    board: &Board,
+    checkpoint: &Checkpoint,
+    options: &SolveOptions,
+) -> Result<SolveReport, CheckpointError> {
+    if with_defender_hand(board, options.defender_hand).hash() != checkpoint.position {
+        return Err(CheckpointError::PositionMismatch);
+    }
+    if options.mudaai != checkpoint.options.mudaai {
+        return Err(CheckpointError::RuleMismatch);
+    }
+    Ok(solve_from(board, options, Some(checkpoint)))
+}
+
+struct Writer {
+    data: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, v: u8) {
+        self.data.push(v);
+    }
+    fn u32(&mut self, v: u32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+    fn u64(&mut self, v: u64) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+    fn i32(&mut self, v: i32) {
+        self.data.extend_from_slice(&v.to_le_bytes());
+    }
+
+    fn checkpoint(&mut self, checkpoint: &Checkpoint) -> io::Result<()> {
+        self.data.extend_from_slice(MAGIC);
+        self.u32(VERSION);
+        self.u64(checkpoint.position);
+        self.i32(checkpoint.depth);
+        self.u64(checkpoint.nodes);
+        self.options(&checkpoint.options)?;
+        self.u64(checkpoint.entries.len() as u64);
+        for (key, entry) in &checkpoint.entries {
+            self.u64(*key);
+            self.entry(entry);
+        }
+        Ok(())
+    }
+
+    fn options(&mut self, options: &SolveOptions) -> io::Result<()> {
+        self.u8(options.max_depth.is_some() as u8);
+        self.i32(options.max_depth.unwrap_or(0));
+        self.u8(options.max_nodes.is_some() as u8);
+        self.u64(options.max_nodes.unwrap_or(0));
+        self.u64(options.tt_memory as u64);
+        self.u8(match options.mudaai {
+            MudaaiRule::Disabled => 0,
+            MudaaiRule::Unprotected => 1,
+        });
+        let counts = match options.defender_hand {
+            DefenderHand::AllRemaining => {
+                self.u8(0);
+                [0; PIECE_TYPES - 1]
+            }
+            DefenderHand::AsGiven => {
+                self.u8(1);
+                [0; PIECE_TYPES - 1]
+            }
+            DefenderHand::Explicit(counts) => {
+                self.u8(2);
+                counts
+            }
+        };
+        self.data.extend_from_slice(&counts);
+        let ordering = &options.ordering;
+        self.u8(ordering.captures_near_king as u8);
+        self.u8(ordering.drops_on_flights as u8);
+        self.u8(ordering.killers as u8);
+        self.u8(ordering.history as u8);
+        self.u8(match ordering.evasions {
+            EvasionOrder::Generated => 0,
+            EvasionOrder::KingMovesFirst => 1,
+            EvasionOrder::InterpositionsFirst => 2,
+        });
+        self.u32(options.threads as u32);
+        match &options.checkpoint {
+            Some(checkpoint) => {
+                self.u8(1);
+                let path = checkpoint.path.to_str().ok_or_else(|| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        "checkpoint path is not valid UTF-8",
+                    )
+                })?;
+                self.u32(path.len() as u32);
+                self.data.extend_from_slice(path.as_bytes());
+                self.u64(checkpoint.interval.as_millis() as u64);
+            }
+            None => self.u8(0),
+        }
+        Ok(())
+    }
+
+    fn hands(&mut self, hands: Hands) {
+        for n in hands.to_counts() {
+            self.u8(n as u8);
+        }
+    }
+
+    fn entry(&mut self, entry: &MinimaxEntry) {
+        self.hands(entry.hands);
+        self.i32(entry.max_depth);
+        match &entry.result {
+            Some(line) => {
+                self.u8(1);
+                self.i32(line.surplus);
+                self.hands(line.pieces);
+                self.u32(line.length as u32);
+                match line.first {
+                    Some(mv) => {
+                        self.u8(1);
+                        self.mv(mv);
+                    }
+                    None => self.u8(0),
+                }
+            }
+            None => self.u8(0),
+        }
+    }
+
+    fn mv(&mut self, mv: Move) {
+        let cell = |p: P| (p.y() * BOARD_SIZE + p.x()) as u8;
+        let (kind, a, b, piece) = match mv {
+            Move::NoPromotion(src, dest) => (0, cell(src), cell(dest), 0),
+            Move::Promotion(src, dest) => (1, cell(src), cell(dest), 0),
+            Move::FromHand(dest, piece) => (2, cell(dest), 0, piece.0),
+        };
+        self.data.extend_from_slice(&[kind, a, b, piece as u8]);
+    }
+}
+
+/// Reader of a checkpoint, which returns `None` on malformed data.
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl Reader<'_> {
+    fn bytes<const N: usize>(&mut self) -> Option<[u8; N]> {
+        if self.data.len() < N {
+            return None;
+        }
+        let (head, rest) = self.data.split_at(N);
+        self.data = rest;
+        head.try_into().ok()
+    }
+    fn u8(&mut self) -> Option<u8> {
+        Some(self.bytes::<1>()?[0])
+    }
+    fn bool(&mut self) -> Option<bool> {
+        match self.u8()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+    fn u32(&mut self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.bytes()?))
+    }
+    fn u64(&mut self) -> Option<u64> {
+        Some(u64::from_le_bytes(self.bytes()?))
+    }
+    fn i32(&mut self) -> Option<i32> {
+        Some(i32::from_le_bytes(self.bytes()?))
+    }
+
+    fn checkpoint(&mut self) -> Option<Checkpoint> {
+        if &self.bytes::<4>()? != MAGIC || self.u32()? != VERSION {
+            return None;
+        }
+        let position = self.u64()?;
+        let depth = self.i32()?;
+        let nodes = self.u64()?;
+        let options = self.options()?;
+        let len = self.u64()?;
+        let mut entries = vec![];
+        for _ in 0..len {
+            let key = self.u64()?;
+            entries.push((key, self.entry()?));
+        }
+        Some(Checkpoint {
+            position,
+            depth,
+            nodes,
+            options,
+            entries,
+        })
+    }
+
+    fn options(&mut self) -> Option<SolveOptions> {
+        let has_max_depth = self.bool()?;
+        let max_depth = Some(self.i32()?).filter(|_| has_max_depth);
+        let has_max_nodes = self.bool()?;
+        let max_nodes = Some(self.u64()?).filter(|_| has_max_nodes);
+        let tt_memory = self.u64()?.min(MAX_TT_MEMORY as u64) as usize;
+        let mudaai = match self.u8()? {
+            0 => MudaaiRule::Disabled,
+            1 => MudaaiRule::Unprotected,
+            _ => return None,
+        };
+        let kind = self.u8()?;
+        let counts = self.bytes::<{ PIECE_TYPES - 1 }>()?;
+        let defender_hand = match kind {
+            0 => DefenderHand::AllRemaining,
+            1 => DefenderHand::AsGiven,
+            2 => DefenderHand::Explicit(counts),
+            _ => return None,
+        };
+        let ordering = MoveOrdering {
+            captures_near_king: self.bool()?,
+            drops_on_flights: self.bool()?,
+            killers: self.bool()?,
+            history: self.bool()?,
+            evasions: match self.u8()? {
+                0 => EvasionOrder::Generated,
+                1 => EvasionOrder::KingMovesFirst,
+                2 => EvasionOrder::InterpositionsFirst,
+                _ => return None,
+            },
+        };
+        let threads = self.u32()? as usize;
+        let checkpoint = if self.bool()? {
+            let len = self.u32()? as usize;
+            if self.data.len() < len {
+                return None;
+            }
+            let (path, rest) = self.data.split_at(len);
+            self.data = rest;
+            let path = PathBuf::from(String::from_utf8(path.to_vec()).ok()?);
+            let interval = Duration::from_millis(self.u64()?);
+            Some(CheckpointOptions { path, interval })
+        } else {
+            None
+        };
+        Some(SolveOptions {
+            max_depth,
+            max_nodes,
+            tt_memory,
+            mudaai,
+            defender_hand,
+            ordering,
+            threads,
+            checkpoint,
+            ..SolveOptions::default()
+        })
+    }
+
+    fn hands(&mut self) -> Option<Hands> {
+        let counts = self.bytes::<{ 2 * PIECE_TYPES }>()?;
+        Hands::from_counts(counts.map(|n| n as i8))
+    }
+
+    fn entry(&mut self) -> Option<MinimaxEntry> {
+        let hands = self.hands()?;
+        let max_depth = self.i32()?;
+        let result = if self.bool()? {
+            let surplus = self.i32()?;
+            let pieces = self.hands()?;
+            let length = self.u32()? as usize;
+            let first = if self.bool()? { Some(self.mv()?) } else { None };
+            // a line of moves must start with a move
+            if first.is_none() != (length == 0) {
+                return None;
+            }
+            Some(Line {
+                first,
+                length,
+                surplus,
+                pieces,
+            })
+        } else {
+            None
+        };
+        Some(MinimaxEntry {
+            hands,
+            max_depth,
+            result,
+        })
+    }
+
+    fn mv(&mut self) -> Option<Move> {
+        let [kind, a, b, piece] = self.bytes::<4>()?;
+        let cell = |c: u8| {
+            let c = c as i32;
+            if c < BOARD_SIZE * BOARD_SIZE {
+                Some(P(c / BOARD_SIZE, c % BOARD_SIZE))
+            } else {
+                None
+            }
+        };
+        match kind {
+            0 | 1 if a == b => None,
+            0 => Some(Move::NoPromotion(cell(a)?, cell(b)?)),
+            1 => Some(Move::Promotion(cell(a)?, cell(b)?)),
+            2 => {
+                // only the pieces other than the king are dropped, and unpromoted
+                let piece = SidedPiece(piece as i8);
+                if piece.is_empty() || !(0..PIECE_KING.0).contains(&piece.to_piece().0) {
+                    return None;
+                }
+                Some(Move::FromHand(cell(a)?, piece))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{solve_with_options, SolveResult, StopReason};
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("tsumeshogi-{}-{}", std::process::id(), name))
+    }
+
+    /// A mate in 9 which takes more than `CHECK_INTERVAL` nodes, so that checkpoints are saved.
+    fn problem() -> Board {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 2), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 0), PIECE_GOLD.as_second());
+        board.set_sided_piece(P(2, 0), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_KNIGHT, 1);
+        board.set_first_hand(PIECE_GOLD, 1);
+        board
+    }
+
+    #[test]
+    fn test_checkpoint_resume() {
+        let board = problem();
+        let path = temp_path("resume");
+        let expected = solve_with_options(&board, &SolveOptions::default());
+
+        for threads in [1, 2] {
+            let options = SolveOptions {
+                max_nodes: Some(expected.stats.nodes / 2),
+                threads,
+                mudaai: MudaaiRule::Unprotected,
+                checkpoint: Some(CheckpointOptions {
+                    path: path.clone(),
+                    interval: Duration::from_secs(3600),
+                }),
+                ..SolveOptions::default()
+            };
+            let report = solve_with_options(&board, &options);
+            assert_eq!(
+                report.result,
+                SolveResult::Unknown {
+                    reason: StopReason::MaxNodes
+                }
+            );
+
+            // saved when the search stopped
+            let checkpoint = Checkpoint::load(&path).unwrap();
+            assert_eq!(checkpoint.depth, report.stats.depth + 2);
+            assert_eq!(checkpoint.nodes, report.stats.nodes);
+            assert_eq!(checkpoint.options.threads, threads);
+            assert_eq!(checkpoint.options.checkpoint, options.checkpoint);
+            assert!(!checkpoint.is_empty());
+
+            let options = SolveOptions {
+                max_nodes: None,
+                checkpoint: None,
+                ..checkpoint.options.clone()
+            };
+            let resumed = resume(&board, &checkpoint, &options).unwrap();
+            assert_eq!(resumed.result, expected.result);
+            assert!(resumed.stats.nodes > checkpoint.nodes);
+            assert_eq!(resumed.stats.depth, expected.stats.depth);
+
+            let mut other = board.clone();
+            other.set_first_hand(PIECE_SILVER, 1);
+            assert!(matches!(
+                resume(&other, &checkpoint, &options),
+                Err(CheckpointError::PositionMismatch)
+            ));
+            let as_given = SolveOptions {
+                defender_hand: DefenderHand::AsGiven,
+                ..options.clone()
+            };
+            assert!(matches!(
+                resume(&board, &checkpoint, &as_given),
+                Err(CheckpointError::PositionMismatch)
+            ));
+            let disabled = SolveOptions {
+                mudaai: MudaaiRule::Disabled,
+                ..options
+            };
+            assert!(matches!(
+                resume(&board, &checkpoint, &disabled),
+                Err(CheckpointError::RuleMismatch)
+            ));
+        }
+
+        fs::write(&path, b"TSCP").unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(CheckpointError::Malformed)
+        ));
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            Checkpoint::load(&path),
+            Err(CheckpointError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_checkpoint_invalid_entries() {
+        let board = problem();
+        let path = temp_path("invalid");
+        let options = SolveOptions::default();
+        let hands = Hands::of(&with_defender_hand(&board, options.defender_hand));
+        let mate = |mv| MinimaxEntry {
+            hands,
+            max_depth: 1,
+            result: Some(Line {
+                first: Some(mv),
+                length: 1,
+                surplus: 0,
+                pieces: hands,
+            }),
+        };
+        let mut rooks = board.clone();
+        rooks.set_first_hand(PIECE_ROOK, 3);
+        let entries = [
+            mate(Move::FromHand(P(0, 0), PIECE_KING.as_first())),
+            mate(Move::FromHand(P(0, 0), PIECE_PROMOTED_ROOK.as_first())),
+            mate(Move::NoPromotion(P(0, 0), P(0, 0))),
+            MinimaxEntry {
+                hands: Hands::of(&rooks),
+                max_depth: 1,
+                result: None,
+            },
+        ];
+        for entry in entries {
+            let checkpoint = Checkpoint::new(board.hash(), 1, 0, &options, vec![(0, entry)]);
+            checkpoint.save(&path).unwrap();
+            assert!(matches!(
+                Checkpoint::load(&path),
+                Err(CheckpointError::Malformed)
+            ));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_periodic_checkpoint() {
+        let board = problem();
+        let path = temp_path("periodic");
+
+        let options = SolveOptions {
+            checkpoint: Some(CheckpointOptions {
+                path: path.clone(),
+                interval: Duration::ZERO,
+            }),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert!(matches!(report.result, SolveResult::Mate { .. }));
+        let checkpoint = Checkpoint::load(&path).unwrap();
+        assert!(checkpoint.nodes > 0 && checkpoint.nodes <= report.stats.nodes);
+        assert!(checkpoint.depth % 2 == 1 && checkpoint.depth <= report.stats.depth);
+        fs::remove_file(&path).unwrap();
+
+        // the saved entries are read back as they were
+        let mut saved = Writer { data: vec![] };
+        saved.checkpoint(&checkpoint).unwrap();
+        let mut reread = Writer { data: vec![] };
+        let mut reader = Reader { data: &saved.data };
+        reread.checkpoint(&reader.checkpoint().unwrap()).unwrap();
+        assert_eq!(saved.data, reread.data);
+    }
+
+    #[test]
+    fn test_checkpoint_options() {
+        let path = temp_path("options");
+        let options = SolveOptions {
+            tt_memory: usize::MAX,
+            ..SolveOptions::default()
+        };
+        let checkpoint = Checkpoint::new(0, 1, 0, &options, vec![]);
+        checkpoint.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        assert_eq!(loaded.options.tt_memory, MAX_TT_MEMORY);
+        fs::remove_file(&path).unwrap();
+
+        #[cfg(unix)]
+        {
+            use std::ffi::OsStr;
+            use std::os::unix::ffi::OsStrExt;
+
+            let options = SolveOptions {
+                checkpoint: Some(CheckpointOptions {
+                    path: PathBuf::from(OsStr::from_bytes(b"tsumeshogi-\xff")),
+                    interval: Duration::ZERO,
+                }),
+                ..SolveOptions::default()
+            };
+            let checkpoint = Checkpoint::new(0, 1, 0, &options, vec![]);
+            let err = checkpoint.save(&path).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+            assert!(!path.exists());
+        }
+    }
+}
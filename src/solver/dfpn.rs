@@ -0,0 +1,702 @@
+//! Depth-first proof-number search (df-pn).
+//!
+//! Nodes where the first player (attacker) is to move are OR nodes, and nodes where the second
+//! player (defender) is to move are AND nodes. A position repeated on the current path is
+//! regarded as not mated, since perpetual checks don't win in tsume shogi.
+//!
+//! Such a disproof depends on the path to the node (the graph history interaction problem):
+//! the same position reached by another path may well be mated. A disproof which depends on a
+//! repetition of an ancestor is therefore stored with the hash of the path, and it is only used
+//! on the same path. Once the search returns to the repeated ancestor, its disproof holds
+//! regardless of the path. Proofs never depend on repetitions.
+
+use super::hand::Hands;
+use super::mudaai::{after_capture, MudaaiRule};
+use super::tt::{TTValue, TranspositionTable, AND_NODE_SALT};
+use super::{check_position, with_defender_hand, InvalidPosition, SolveOptions};
+use crate::board::Board;
+use crate::common::*;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Proof and disproof numbers at least this large are regarded as infinite.
+const INF: u64 = 1 << 40;
+
+/// Depth meaning that the search is not bounded.
+const UNBOUNDED: u32 = u32::MAX;
+
+/// Proof and disproof numbers of a node, stored in the transposition table.
+#[derive(Clone, Copy, Debug)]
+pub struct DfpnEntry {
+    hands: Hands,
+    pn: u64,
+    dn: u64,
+    /// For a disproof depending on a repetition, the ply of the shallowest repeated ancestor
+    /// and the hash of the path to the node.
+    repetition: Option<(u32, u64)>,
+}
+
+impl DfpnEntry {
+    /// Whether the entry gives the numbers of the position with `hands` reached by the path
+    /// with `path_hash`: the entry for the same hands, a proof with hands worse for the first
+    /// player, or a disproof with better ones. A disproof depending on a repetition only holds
+    /// on the same path.
+    fn answers(&self, hands: Hands, path_hash: u64) -> bool {
+        if self.repetition.is_some_and(|(_, hash)| hash != path_hash) {
+            return false;
+        }
+        self.hands == hands
+            || (self.pn == 0 && hands.dominates(&self.hands))
+            || (self.dn == 0 && self.hands.dominates(&hands))
+    }
+}
+
+impl TTValue for DfpnEntry {
+    /// Resolved nodes are kept first, and then nodes with larger numbers, which took more work.
+    fn priority(&self) -> u64 {
+        if self.pn == 0 || self.dn == 0 {
+            u64::MAX
+        } else {
+            self.pn.saturating_add(self.dn)
+        }
+    }
+}
+
+/// Proof and disproof numbers.
+type Numbers = (u64, u64);
+
+const UNKNOWN: Numbers = (1, 1);
+const PROVEN: Numbers = (0, INF);
+const DISPROVEN: Numbers = (INF, 0);
+
+/// Keys of a node. Nodes searched with a depth bound are distinguished by the remaining depth.
+struct NodeKey {
+    /// Key in the transposition table, which doesn't depend on the hands.
+    table: u64,
+    /// Key of the whole position to detect repetitions.
+    path: u64,
+    hands: Hands,
+}
+
+impl NodeKey {
+    fn new(board: &Board, or_node: bool, depth: u32) -> NodeKey {
+        let mut salt = 0;
+        if !or_node {
+            salt ^= AND_NODE_SALT;
+        }
+        if depth != UNBOUNDED {
+            salt ^= (depth as u64 + 1).wrapping_mul(0x9e3779b97f4a7c15);
+        }
+        NodeKey {
+            table: board.board_hash() ^ salt,
+            path: board.hash() ^ salt,
+            hands: Hands::of(board),
+        }
+    }
+}
+
+fn child_depth(depth: u32) -> u32 {
+    if depth == UNBOUNDED {
+        UNBOUNDED
+    } else {
+        depth - 1
+    }
+}
+
+struct Dfpn<'a> {
+    table: &'a mut TranspositionTable<DfpnEntry>,
+    /// Positions on the current path with their plies.
+    path: HashMap<u64, u32>,
+    /// Hashes of the current path up to each ply.
+    path_hashes: Vec<u64>,
+    mudaai: MudaaiRule,
+}
+
+impl<'a> Dfpn<'a> {
+    fn new(table: &'a mut TranspositionTable<DfpnEntry>, mudaai: MudaaiRule) -> Dfpn<'a> {
+        Dfpn {
+            table,
+            path: HashMap::new(),
+            path_hashes: vec![],
+            mudaai,
+        }
+    }
+
+    /// Ply of the node if it is entered next.
+    fn ply(&self) -> u32 {
+        self.path_hashes.len() as u32
+    }
+
+    /// Hash of the current path extended by the node.
+    fn path_hash(&self, key: &NodeKey) -> u64 {
+        let prev = self.path_hashes.last().copied().unwrap_or(0);
+        (prev.rotate_left(17) ^ key.path).wrapping_mul(0x9e3779b97f4a7c15)
+    }
+
+    fn enter(&mut self, key: &NodeKey) {
+        let hash = self.path_hash(key);
+        self.path.insert(key.path, self.ply());
+        self.path_hashes.push(hash);
+    }
+
+    fn leave(&mut self, key: &NodeKey) {
+        self.path.remove(&key.path);
+        self.path_hashes.pop();
+    }
+
+    fn lookup(&mut self, key: &NodeKey) -> Numbers {
+        self.probe(key).0
+    }
+
+    /// Returns the numbers of the node, the proof or disproof bounds if it is resolved,
+    /// and the ply of the shallowest repeated ancestor if the disproof depends on repetitions.
+    fn probe(&mut self, key: &NodeKey) -> (Numbers, Hands, Option<u32>) {
+        if let Some(&ply) = self.path.get(&key.path) {
+            // repetition: the defender escapes
+            return (DISPROVEN, key.hands, Some(ply));
+        }
+        let path_hash = self.path_hash(key);
+        self.table
+            .find(key.table, |e| e.answers(key.hands, path_hash))
+            .map_or((UNKNOWN, key.hands, None), |e| {
+                ((e.pn, e.dn), e.hands, e.repetition.map(|(ply, _)| ply))
+            })
+    }
+
+    /// Stores the numbers of the node. Resolved nodes are stored with the proof or disproof
+    /// bounds `hands`, and the others with the hands of the node. A disproof depending on the
+    /// repetition of the ancestor at `repetition` is stored for the current path, which must
+    /// end with the node.
+    fn store(&mut self, key: &NodeKey, (pn, dn): Numbers, hands: Hands, repetition: Option<u32>) {
+        let actual = key.hands;
+        let repetition = repetition.map(|ply| (ply, *self.path_hashes.last().unwrap()));
+        let entry = DfpnEntry {
+            hands,
+            pn,
+            dn,
+            repetition,
+        };
+        self.table
+            .insert_by(key.table, entry, |e| e.hands == actual);
+    }
+
+    /// Returns the moves of the node, and whether its proof or disproof may be generalized to
+    /// other hands by `Hands::proof` and `Hands::disproof`.
+    ///
+    /// Without a depth bound, every evasion is searched, since futile interpositions don't
+    /// change whether the mate exists. With a bound, a candidate of a futile interposition is
+    /// excluded if the capture of the interposed piece mates within the length of the mate
+    /// against the other defences.
+    fn moves(&mut self, board: &Board, or_node: bool, depth: u32) -> (Vec<Move>, bool) {
+        if or_node {
+            return (board.enumerate_check(), true);
+        }
+        if depth == UNBOUNDED || self.mudaai == MudaaiRule::Disabled {
+            return (board.enumerate_check_avoidance(), true);
+        }
+        let (moves, captures) = self.mudaai.split_evasions(board);
+        if captures.iter().all(|c| c.is_empty()) {
+            return (moves, true);
+        }
+
+        let defences = moves
+            .iter()
+            .zip(&captures)
+            .filter(|(_, c)| c.is_empty())
+            .map(|(&mv, _)| {
+                let mut child = board.clone();
+                child.apply_move(mv);
+                child
+            })
+            .collect::<Vec<_>>();
+        let length = if defences.is_empty() {
+            0
+        } else {
+            let length = (2..=depth).step_by(2).find(|&len| {
+                defences
+                    .iter()
+                    .all(|child| self.prove(child, true, len - 1))
+            });
+            match length {
+                Some(length) => length,
+                // a defence refutes the mate anyway
+                None => return (moves, true),
+            }
+        };
+        let moves = moves
+            .into_iter()
+            .zip(captures)
+            .filter(|(mv, captures)| {
+                !captures
+                    .iter()
+                    .any(|&c| self.prove(&after_capture(board, *mv, c), false, length))
+            })
+            .map(|(mv, _)| mv)
+            .collect();
+        (moves, false)
+    }
+
+    /// Expands the node until its proof number reaches `th_pn` or its disproof number reaches `th_dn`.
+    /// If `depth` is not `UNBOUNDED`, the attacker has to mate within `depth` plies.
+    fn mid(&mut self, board: &Board, or_node: bool, depth: u32, th_pn: u64, th_dn: u64) {
+        let key = NodeKey::new(board, or_node, depth);
+        if depth == 0 {
+            if or_node {
+                self.store(&key, DISPROVEN, Hands::ANY_DISPROOF, None);
+            } else {
+                let (moves, exact) = self.moves(board, false, depth);
+                match moves.first() {
+                    None => {
+                        let hands = if exact {
+                            Hands::proof(board, false, &[])
+                        } else {
+                            key.hands
+                        };
+                        self.store(&key, PROVEN, hands, None);
+                    }
+                    Some(&mv) => {
+                        // the children out of the bound are disproven for any hands
+                        let child = Hands::ANY_DISPROOF.before(board, mv);
+                        let hands = if exact {
+                            Hands::disproof(board, false, &[child])
+                        } else {
+                            key.hands
+                        };
+                        self.store(&key, DISPROVEN, hands, None);
+                    }
+                }
+            }
+            return;
+        }
+        let (moves, exact) = self.moves(board, or_node, depth);
+        if moves.is_empty() {
+            if or_node {
+                let hands = Hands::disproof(board, true, &[]);
+                self.store(&key, DISPROVEN, hands, None);
+            } else {
+                let hands = if exact {
+                    Hands::proof(board, false, &[])
+                } else {
+                    key.hands
+                };
+                self.store(&key, PROVEN, hands, None);
+            }
+            return;
+        }
+
+        let children = moves
+            .iter()
+            .map(|&mv| {
+                let mut child = board.clone();
+                child.apply_move(mv);
+                let key = NodeKey::new(&child, !or_node, child_depth(depth));
+                (child, key)
+            })
+            .collect::<Vec<_>>();
+
+        let ply = self.ply();
+        self.enter(&key);
+        loop {
+            // `phi` is the number to be minimized by the player to move, and `delta` the other one.
+            let mut delta = 0u64;
+            let mut phi = INF;
+            let mut best = 0;
+            let mut phi2 = INF;
+            let mut best_delta = 0;
+            let mut child_hands = vec![];
+            let mut child_repetitions = vec![];
+            for (i, (_, child_key)) in children.iter().enumerate() {
+                let ((pn, dn), hands, repetition) = self.probe(child_key);
+                child_hands.push(hands.before(board, moves[i]));
+                child_repetitions.push(repetition);
+                let (c_phi, c_delta) = if or_node { (pn, dn) } else { (dn, pn) };
+                delta = (delta + c_delta).min(INF);
+                if c_phi < phi {
+                    phi2 = phi;
+                    phi = c_phi;
+                    best = i;
+                    best_delta = c_delta;
+                } else if c_phi < phi2 {
+                    phi2 = c_phi;
+                }
+            }
+            let (pn, dn) = if or_node { (phi, delta) } else { (delta, phi) };
+            // a proof at an OR node and a disproof at an AND node only need the best child
+            let mut repetition = None;
+            let hands = if (pn == 0 || dn == 0) && !exact {
+                // futile interpositions are excluded for these hands only
+                key.hands
+            } else if pn == 0 {
+                let children = if or_node {
+                    &child_hands[best..=best]
+                } else {
+                    &child_hands
+                };
+                Hands::proof(board, or_node, children)
+            } else if dn == 0 {
+                let range = if or_node {
+                    0..children.len()
+                } else {
+                    best..best + 1
+                };
+                // repetitions of this node don't depend on the path to it
+                repetition = child_repetitions[range.clone()]
+                    .iter()
+                    .flatten()
+                    .copied()
+                    .min()
+                    .filter(|&p| p < ply);
+                Hands::disproof(board, or_node, &child_hands[range])
+            } else {
+                key.hands
+            };
+            self.store(&key, (pn, dn), hands, repetition);
+
+            if pn >= th_pn || dn >= th_dn {
+                break;
+            }
+
+            let (th_phi, th_delta) = if or_node {
+                (th_pn, th_dn)
+            } else {
+                (th_dn, th_pn)
+            };
+            // thresholds for the child's number minimized by the player to move here, and the other one
+            let child_th_own = th_phi.min(phi2 + 1);
+            let child_th_other = th_delta - delta + best_delta;
+            let (child_th_pn, child_th_dn) = if or_node {
+                (child_th_own, child_th_other)
+            } else {
+                (child_th_other, child_th_own)
+            };
+            let (child, _) = &children[best];
+            self.mid(
+                child,
+                !or_node,
+                child_depth(depth),
+                child_th_pn,
+                child_th_dn,
+            );
+        }
+        self.leave(&key);
+    }
+
+    /// Searches the node completely and returns whether it is proven.
+    fn prove(&mut self, board: &Board, or_node: bool, depth: u32) -> bool {
+        let key = NodeKey::new(board, or_node, depth);
+        let (pn, dn) = self.lookup(&key);
+        if pn != 0 && dn != 0 {
+            self.mid(board, or_node, depth, INF, INF);
+        }
+        self.lookup(&key).0 == 0
+    }
+
+    /// Computes an upper bound of the mate length of a proven node, following the attacker
+    /// moves proven in the table. The entries of the proof may have been replaced by other
+    /// positions with the same board, and then the nodes are proven again.
+    fn proven_length(
+        &mut self,
+        board: &Board,
+        or_node: bool,
+        memo: &mut HashMap<u64, u32>,
+    ) -> Option<u32> {
+        let key = NodeKey::new(board, or_node, UNBOUNDED);
+        if let Some(&len) = memo.get(&key.path) {
+            return Some(len);
+        }
+        if self.path.contains_key(&key.path) || !self.prove(board, or_node, UNBOUNDED) {
+            return None;
+        }
+
+        self.enter(&key);
+        let mut ret = if or_node { None } else { Some(0) };
+        let mut unproven = vec![];
+        for mv in self.moves(board, or_node, UNBOUNDED).0 {
+            let mut child = board.clone();
+            child.apply_move(mv);
+            if or_node && self.lookup(&NodeKey::new(&child, false, UNBOUNDED)).0 != 0 {
+                unproven.push(child);
+                continue;
+            }
+            match (self.proven_length(&child, !or_node, memo), ret) {
+                (Some(len), Some(cur)) if or_node => ret = Some(cur.min(len + 1)),
+                (Some(len), None) if or_node => ret = Some(len + 1),
+                (Some(len), Some(cur)) => ret = Some(cur.max(len + 1)),
+                (None, _) if or_node => (),
+                _ => {
+                    ret = None;
+                    break;
+                }
+            }
+        }
+        if or_node && ret.is_none() {
+            // the proven child has been replaced in the table
+            ret = unproven
+                .iter()
+                .find_map(|child| self.proven_length(child, false, memo))
+                .map(|len| len + 1);
+        }
+        self.leave(&key);
+
+        // a failure may depend on the path, so only the lengths are kept
+        if let Some(len) = ret {
+            memo.insert(key.path, len);
+        }
+        ret
+    }
+
+    /// Extracts the main line of a node which is mated in exactly `depth` plies:
+    /// the first attacker move that keeps the mate within `depth` plies,
+    /// and the first defender move that makes the mate longest.
+    /// Returns `false` if no such move is found, which means the searches with the depth bounds
+    /// are inconsistent, e.g. because of disproofs depending on the path.
+    fn main_line(
+        &mut self,
+        board: &Board,
+        or_node: bool,
+        depth: u32,
+        line: &mut Vec<Move>,
+    ) -> bool {
+        if depth == 0 {
+            return true;
+        }
+        for mv in self.moves(board, or_node, depth).0 {
+            let mut child = board.clone();
+            child.apply_move(mv);
+            let found = if or_node {
+                self.prove(&child, false, depth - 1)
+            } else {
+                depth < 3 || !self.prove(&child, true, depth - 3)
+            };
+            if found {
+                line.push(mv);
+                return self.main_line(&child, !or_node, depth - 1, line);
+            }
+        }
+        false
+    }
+}
+
+/// Reason why `solve_dfpn_with_options` fails.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DfpnError {
+    InvalidPosition(InvalidPosition),
+    /// The mate is proven, but its main line could not be extracted.
+    NoMainLine,
+}
+
+impl fmt::Display for DfpnError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DfpnError::InvalidPosition(reason) => write!(f, "invalid position ({})", reason),
+            DfpnError::NoMainLine => write!(f, "the main line of the mate could not be extracted"),
+        }
+    }
+}
+
+impl std::error::Error for DfpnError {}
+
+/// Solves the tsume shogi problem by df-pn without a depth bound.
+/// The second player is assumed to have all the remaining pieces.
+/// Returns the main line if the problem has a mate, and `None` otherwise,
+/// including when the position is invalid.
+///
+/// After the mate is proven, the shortest mate is found by searching again with depth bounds
+/// shorter than the mate found so far, and then the main line is extracted with these bounds.
+pub fn solve_dfpn(board: &Board) -> Option<Vec<Move>> {
+    solve_dfpn_with_table(board, &mut TranspositionTable::default())
+}
+
+/// Same as `solve_dfpn`, but uses the given transposition table.
+pub fn solve_dfpn_with_table(
+    board: &Board,
+    table: &mut TranspositionTable<DfpnEntry>,
+) -> Option<Vec<Move>> {
+    solve_dfpn_with_options(board, &SolveOptions::default(), table)
+        .ok()
+        .flatten()
+}
+
+/// Same as `solve_dfpn_with_table`, but with the mudaai rule and the second player's hand of
+/// `options`. The other options don't apply to df-pn.
+/// Returns `Ok(None)` if the problem has no mate.
+pub fn solve_dfpn_with_options(
+    board: &Board,
+    options: &SolveOptions,
+    table: &mut TranspositionTable<DfpnEntry>,
+) -> Result<Option<Vec<Move>>, DfpnError> {
+    check_position(board, options.defender_hand).map_err(DfpnError::InvalidPosition)?;
+    let board = with_defender_hand(board, options.defender_hand);
+
+    let mut dfpn = Dfpn::new(table, options.mudaai);
+    if !dfpn.prove(&board, true, UNBOUNDED) {
+        return Ok(None);
+    }
+    let mut length = dfpn
+        .proven_length(&board, true, &mut HashMap::new())
+        .ok_or(DfpnError::NoMainLine)?;
+    while length >= 3 && dfpn.prove(&board, true, length - 2) {
+        length -= 2;
+    }
+
+    let mut line = vec![];
+    if !dfpn.main_line(&board, true, length, &mut line) {
+        return Err(DfpnError::NoMainLine);
+    }
+    Ok(Some(line))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{with_all_remaining_pieces, DefenderHand};
+    use super::*;
+    use crate::solver::solve;
+    use crate::P;
+
+    #[test]
+    fn test_dfpn() {
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+            board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+            board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+
+            let sol = solve_dfpn(&board);
+            assert_eq!(sol, solve(&board, 3));
+        }
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+            board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+            board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+
+            let sol = solve_dfpn(&board);
+            assert!(sol.is_some() && sol.unwrap().len() == 3);
+        }
+        {
+            // no mate: the dropped gold is captured by the king
+            let mut board = Board::new();
+            board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+            board.set_first_hand(PIECE_GOLD, 1);
+
+            assert_eq!(solve_dfpn(&board), None);
+        }
+    }
+
+    #[test]
+    fn test_dfpn_hand_superiority() {
+        let mut table = TranspositionTable::default();
+        let mut dfpn = Dfpn::new(&mut table, MudaaiRule::default());
+
+        // a proof also proves the position with more pieces in the first player's hand
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+        assert!(dfpn.prove(&board, true, UNBOUNDED));
+        board.set_first_hand(PIECE_PAWN, 1);
+        assert_eq!(dfpn.lookup(&NodeKey::new(&board, true, UNBOUNDED)), PROVEN);
+        // the proof pieces are empty, and the second player's hand doesn't matter either
+        board.set_first_hand(PIECE_PAWN, 0);
+        board.set_second_hand(PIECE_PAWN, 1);
+        assert_eq!(dfpn.lookup(&NodeKey::new(&board, true, UNBOUNDED)), PROVEN);
+
+        // ▲１三香打 mates only if the second player has no piece to interpose at １二
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 8), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 6), PIECE_GOLD.as_first());
+        board.set_first_hand(PIECE_LANCE, 1);
+        assert!(dfpn.prove(&board, true, UNBOUNDED));
+        board.set_first_hand(PIECE_SILVER, 1);
+        assert_eq!(dfpn.lookup(&NodeKey::new(&board, true, UNBOUNDED)), PROVEN);
+        board.set_second_hand(PIECE_PAWN, 1);
+        assert_eq!(dfpn.lookup(&NodeKey::new(&board, true, UNBOUNDED)), UNKNOWN);
+
+        // a disproof also disproves the position with fewer pieces
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_GOLD, 1);
+        assert!(!dfpn.prove(&board, true, UNBOUNDED));
+        board.set_first_hand(PIECE_GOLD, 0);
+        assert_eq!(
+            dfpn.lookup(&NodeKey::new(&board, true, UNBOUNDED)),
+            DISPROVEN
+        );
+    }
+
+    #[test]
+    fn test_dfpn_ghi() {
+        let mut table = TranspositionTable::default();
+        let mut dfpn = Dfpn::new(&mut table, MudaaiRule::default());
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+        let board = with_all_remaining_pieces(&board);
+        let key = NodeKey::new(&board, true, UNBOUNDED);
+
+        // the mate passes through the position after two plies, which is a repetition
+        // if the search reaches the problem from it
+        let line = solve(&board, 3).unwrap();
+        let mut ancestor = board.clone();
+        ancestor.apply_move(line[0]);
+        ancestor.apply_move(line[1]);
+        let ancestor = NodeKey::new(&ancestor, true, UNBOUNDED);
+        dfpn.enter(&ancestor);
+        assert!(!dfpn.prove(&board, true, UNBOUNDED));
+        assert_eq!(dfpn.lookup(&key), DISPROVEN);
+        dfpn.leave(&ancestor);
+
+        // the disproof doesn't hold on another path
+        assert_ne!(dfpn.lookup(&key), DISPROVEN);
+        assert!(dfpn.prove(&board, true, UNBOUNDED));
+    }
+
+    #[test]
+    fn test_dfpn_ghi_problem() {
+        // the king is chased out of the corner and the search meets the same positions by
+        // different paths; if the disproofs by repetitions were used on other paths, the
+        // search of this mate wouldn't finish
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 8), PIECE_KING.as_second());
+        board.set_sided_piece(P(3, 3), PIECE_BISHOP.as_first());
+        board.set_sided_piece(P(5, 7), PIECE_SILVER.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        let options = SolveOptions {
+            defender_hand: DefenderHand::AsGiven,
+            ..SolveOptions::default()
+        };
+
+        let mut table = TranspositionTable::default();
+        let sol = solve_dfpn_with_options(&board, &options, &mut table).unwrap();
+        assert_eq!(sol.map(|s| s.len()), Some(9));
+    }
+
+    #[test]
+    fn test_dfpn_replaced_entries() {
+        // many positions with the same board but different hands replace each other in the
+        // table, so the proof has to be searched again to find the mate length
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 2), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 0), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+
+        let sol = solve_dfpn(&board);
+        assert_eq!(sol.map(|s| s.len()), Some(7));
+    }
+
+    #[test]
+    fn test_dfpn_long_mate() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+
+        assert_eq!(solve(&board, 3), None);
+        let sol = solve_dfpn(&board);
+        assert!(sol.is_some());
+        assert_eq!(sol.map(|s| s.len()), solve(&board, 5).map(|s| s.len()));
+    }
+}
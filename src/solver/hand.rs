@@ -0,0 +1,241 @@
+//! Superiority relation of hands (優越関係).
+//!
+//! If the first player mates with some pieces in hand, it also mates with more pieces in hand
+//! on the same board, as long as the second player doesn't get more pieces in hand.
+//! The transposition tables key positions by the board only and compare the hands with this
+//! relation, so that a proof also proves the positions where the first player has more pieces,
+//! and a disproof also disproves the ones where it has fewer.
+//!
+//! Resolved entries are generalized further by proof pieces and disproof pieces: a proof is
+//! stored with the fewest pieces the first player needs (and the most pieces the second player
+//! may have), and a disproof with the most pieces the first player may have (and the fewest
+//! the second player needs).
+
+use crate::board::Board;
+use crate::common::*;
+
+/// Count meaning any number of pieces.
+const UNLIMITED: i8 = i8::MAX;
+
+/// Pieces in the hands of both players.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Hands {
+    first: [i8; PIECE_TYPES],
+    second: [i8; PIECE_TYPES],
+}
+
+impl Hands {
+    /// Bounds of a disproof which holds for any hands, e.g. of a node out of the depth bound.
+    pub const ANY_DISPROOF: Hands = Hands {
+        first: [UNLIMITED; PIECE_TYPES],
+        second: [0; PIECE_TYPES],
+    };
+
+    pub fn of(board: &Board) -> Hands {
+        let mut ret = Hands {
+            first: [0; PIECE_TYPES],
+            second: [0; PIECE_TYPES],
+        };
+        for i in 0..PIECE_TYPES {
+            ret.first[i] = board.get_first_hand(Piece(i as i8));
+            ret.second[i] = board.get_second_hand(Piece(i as i8));
+        }
+        ret
+    }
+
+    /// Whether the first player is at least as well off as in `other`: it has at least as many
+    /// pieces of each kind, and the second player has at most as many.
+    pub fn dominates(&self, other: &Hands) -> bool {
+        (0..PIECE_TYPES)
+            .all(|i| self.first[i] >= other.first[i] && self.second[i] <= other.second[i])
+    }
+
+    /// Bounds of a proof (or a disproof) of a node whose only child is proven (or disproven)
+    /// with these bounds, where the child is reached by `mv` on `board`.
+    pub fn before(&self, board: &Board, mv: Move) -> Hands {
+        let mut ret = *self;
+        let moving = match mv {
+            Move::FromHand(_, piece) => piece,
+            Move::NoPromotion(src, _) | Move::Promotion(src, _) => board.get_sided_piece(src),
+        };
+        let player_hand = if moving.is_first() {
+            &mut ret.first
+        } else {
+            &mut ret.second
+        };
+        match mv {
+            Move::FromHand(_, piece) => add(player_hand, piece.to_piece(), 1),
+            Move::NoPromotion(_, dest) | Move::Promotion(_, dest) => {
+                let captured = board.get_sided_piece(dest);
+                if !captured.is_empty() {
+                    add(player_hand, captured.to_piece().capture(), -1);
+                }
+            }
+        }
+        ret
+    }
+
+    /// Bounds of the proof of a node whose children are all proven with `children`.
+    /// At an OR node, `children` is the proven child only.
+    pub fn proof(board: &Board, or_node: bool, children: &[Hands]) -> Hands {
+        let mut ret = Hands {
+            first: [0; PIECE_TYPES],
+            second: [UNLIMITED; PIECE_TYPES],
+        };
+        for child in children {
+            for i in 0..PIECE_TYPES {
+                ret.first[i] = ret.first[i].max(child.first[i]);
+                ret.second[i] = ret.second[i].min(child.second[i]);
+            }
+        }
+        if !or_node {
+            // the second player must not get pieces to interpose, even futile ones
+            let actual = Hands::of(board);
+            for piece in missing_drops(board, false) {
+                ret.second[piece.0 as usize] = actual.second[piece.0 as usize];
+            }
+        }
+        ret
+    }
+
+    /// Bounds of the disproof of a node whose children are all disproven with `children`.
+    /// At an AND node, `children` is the disproven child only.
+    pub fn disproof(board: &Board, or_node: bool, children: &[Hands]) -> Hands {
+        let mut ret = Hands {
+            first: [UNLIMITED; PIECE_TYPES],
+            second: [0; PIECE_TYPES],
+        };
+        for child in children {
+            for i in 0..PIECE_TYPES {
+                ret.first[i] = ret.first[i].min(child.first[i]);
+                ret.second[i] = ret.second[i].max(child.second[i]);
+            }
+        }
+        if or_node {
+            // the first player must not get pieces to drop with a check
+            let actual = Hands::of(board);
+            for piece in missing_drops(board, true) {
+                ret.first[piece.0 as usize] = actual.first[piece.0 as usize];
+            }
+        }
+        ret
+    }
+
+    /// Pieces in the first player's hand, one item for each piece.
+    pub fn first_pieces(&self) -> Vec<Piece> {
+        (0..(PIECE_TYPES - 1))
+            .flat_map(|i| (0..self.first[i]).map(move |_| Piece(i as i8)))
+            .collect()
+    }
+
+    /// Counts of the first player's pieces followed by the second player's, e.g. to save them.
+    pub(super) fn to_counts(self) -> [i8; 2 * PIECE_TYPES] {
+        let mut ret = [0; 2 * PIECE_TYPES];
+        ret[..PIECE_TYPES].copy_from_slice(&self.first);
+        ret[PIECE_TYPES..].copy_from_slice(&self.second);
+        ret
+    }
+
+    /// Inverse of `to_counts`. Returns `None` if a count is neither unlimited nor possible
+    /// with the pieces of a game, e.g. when it is loaded from a corrupted file.
+    pub(super) fn from_counts(counts: [i8; 2 * PIECE_TYPES]) -> Option<Hands> {
+        let valid = counts.iter().enumerate().all(|(i, &n)| {
+            let max = PIECE_COUNTS.get(i % PIECE_TYPES).copied().unwrap_or(0);
+            n == UNLIMITED || (0..=max).contains(&n)
+        });
+        if !valid {
+            return None;
+        }
+        let mut ret = Hands::ANY_DISPROOF;
+        ret.first.copy_from_slice(&counts[..PIECE_TYPES]);
+        ret.second.copy_from_slice(&counts[PIECE_TYPES..]);
+        Some(ret)
+    }
+}
+
+fn add(hand: &mut [i8; PIECE_TYPES], piece: Piece, n: i8) {
+    let count = &mut hand[piece.0 as usize];
+    if *count != UNLIMITED {
+        *count = (*count + n).max(0);
+    }
+}
+
+/// Returns the kinds of pieces which the player to move doesn't have in hand, but could drop
+/// to check (if `or_node`) or to avoid the check (otherwise).
+fn missing_drops(board: &Board, or_node: bool) -> Vec<Piece> {
+    let mut with_all = board.clone();
+    let mut missing = vec![];
+    for i in 0..(PIECE_TYPES - 1) {
+        let piece = Piece(i as i8);
+        if or_node && board.get_first_hand(piece) == 0 {
+            with_all.set_first_hand(piece, 1);
+            missing.push(piece);
+        } else if !or_node && board.get_second_hand(piece) == 0 {
+            with_all.set_second_hand(piece, 1);
+            missing.push(piece);
+        }
+    }
+    if missing.is_empty() {
+        return missing;
+    }
+    let moves = if or_node {
+        with_all.enumerate_check()
+    } else {
+        with_all.enumerate_check_avoidance()
+    };
+    missing.retain(|&piece| {
+        moves.iter().any(|mv| match mv {
+            Move::FromHand(_, p) => p.to_piece() == piece,
+            _ => false,
+        })
+    });
+    missing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_dominates() {
+        let mut board = Board::new();
+        board.set_first_hand(PIECE_GOLD, 1);
+        board.set_second_hand(PIECE_PAWN, 2);
+        let hands = Hands::of(&board);
+
+        let mut more = board.clone();
+        more.set_first_hand(PIECE_SILVER, 1);
+        more.set_second_hand(PIECE_PAWN, 1);
+        let more = Hands::of(&more);
+        assert!(more.dominates(&hands));
+        assert!(!hands.dominates(&more));
+        assert!(hands.dominates(&hands));
+
+        // the second player has more pieces, so neither is better for the first player
+        let mut other = board.clone();
+        other.set_first_hand(PIECE_SILVER, 1);
+        other.set_second_hand(PIECE_LANCE, 1);
+        let other = Hands::of(&other);
+        assert!(!other.dominates(&hands));
+        assert!(!hands.dominates(&other));
+    }
+
+    #[test]
+    fn test_before() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 4), PIECE_PAWN.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_SILVER.as_first());
+
+        // a drop needs the dropped piece, and a capture provides the captured piece
+        let proof = Hands::proof(&board, false, &[]);
+        let drop = Move::FromHand(P(1, 3), PIECE_GOLD.as_first());
+        assert_eq!(proof.before(&board, drop).first_pieces(), vec![PIECE_GOLD]);
+        let capture = Move::NoPromotion(P(2, 4), P(1, 4));
+        assert_eq!(proof.before(&board, capture), proof);
+        let mut child = Hands::of(&board);
+        child.first[PIECE_PAWN.0 as usize] = 1;
+        assert_eq!(child.before(&board, capture), Hands::of(&board));
+    }
+}
@@ -0,0 +1,331 @@
+//! Helpmate problems (bakazume), where both players cooperate to mate the second player.
+//!
+//! The first player must check on every move and the second player must avoid the check, with
+//! the same move generators as tsume, but the second player chooses the evasions leading to
+//! the shortest mate. Different orders of the moves are different solutions.
+//!
+//! The mudaai rule doesn't apply: an interposition captured for free can be part of the
+//! cooperation.
+
+use super::tt::{TTValue, TranspositionTable, AND_NODE_SALT};
+use super::{
+    check_position, with_defender_hand, SolveOptions, SolveResult, SolveStats, StopReason,
+};
+use crate::board::Board;
+use crate::common::*;
+use std::time::Instant;
+
+/// Solutions are counted up to this number, which is enough to tell whether one is unique.
+const MAX_SOLUTIONS: u32 = 2;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HelpmateReport {
+    /// The shortest helpmate, the first one in the order of the move generators if there are
+    /// several. `pieces_used` is the pieces in the first player's hand dropped along it.
+    pub result: SolveResult,
+    /// Number of the shortest helpmates, counted up to 2.
+    pub solutions: u32,
+    pub stats: SolveStats,
+}
+
+impl HelpmateReport {
+    /// Whether there is exactly one shortest helpmate.
+    pub fn is_unique(&self) -> bool {
+        matches!(self.result, SolveResult::Mate { .. }) && self.solutions == 1
+    }
+}
+
+/// Number of helpmates from a node, stored in the transposition table.
+#[derive(Clone, Debug)]
+struct HelpmateEntry {
+    /// The number of plies searched, or `i32::MAX` if there is no helpmate at any depth.
+    max_depth: i32,
+    /// Number of the helpmates within `max_depth`, up to `MAX_SOLUTIONS`.
+    solutions: u32,
+    /// Whether some line was cut off by the depth limit.
+    cutoff: bool,
+}
+
+impl TTValue for HelpmateEntry {
+    fn priority(&self) -> u64 {
+        self.max_depth as u64
+    }
+}
+
+/// Depth-limited search counting the helpmates.
+struct Helpmate<'a> {
+    options: &'a SolveOptions,
+    tt: TranspositionTable<HelpmateEntry>,
+    nodes: u64,
+    /// Depth of the current iteration.
+    root_depth: i32,
+    /// The largest number of plies from the root among the nodes visited.
+    max_ply: i32,
+    stopped: Option<StopReason>,
+    /// Whether some line was cut off by the depth limit.
+    cutoff: bool,
+}
+
+impl Helpmate<'_> {
+    /// Returns the number of the helpmates within `max_depth` plies, up to `MAX_SOLUTIONS`.
+    /// `board` is after a check of the first player unless `or_node`.
+    fn count(&mut self, board: &Board, or_node: bool, max_depth: i32) -> u32 {
+        if self.stopped.is_some() {
+            return 0;
+        }
+        self.nodes += 1;
+        self.max_ply = self.max_ply.max(self.root_depth - max_depth);
+        self.stopped = self.options.check_stop(self.nodes);
+        if self.stopped.is_some() {
+            return 0;
+        }
+
+        let moves = if or_node {
+            board.enumerate_check()
+        } else {
+            board.enumerate_check_avoidance()
+        };
+        if !or_node && moves.is_empty() {
+            return 1;
+        }
+        if max_depth == 0 {
+            self.cutoff = true;
+            return 0;
+        }
+
+        let key = if or_node {
+            board.hash()
+        } else {
+            board.hash() ^ AND_NODE_SALT
+        };
+        let answers = |e: &HelpmateEntry| {
+            e.max_depth == max_depth || (e.max_depth == i32::MAX && e.solutions == 0)
+        };
+        if let Some(entry) = self.tt.find(key, answers) {
+            self.cutoff |= entry.cutoff;
+            return entry.solutions;
+        }
+        let outer_cutoff = std::mem::replace(&mut self.cutoff, false);
+
+        let mut solutions = 0;
+        for mv in moves {
+            let mut child = board.clone();
+            child.apply_move(mv);
+            solutions += self.count(&child, !or_node, max_depth - 1);
+            if self.stopped.is_some() {
+                return 0;
+            }
+            if solutions >= MAX_SOLUTIONS {
+                solutions = MAX_SOLUTIONS;
+                break;
+            }
+        }
+
+        let entry = HelpmateEntry {
+            max_depth: if solutions == 0 && !self.cutoff {
+                i32::MAX
+            } else {
+                max_depth
+            },
+            solutions,
+            cutoff: self.cutoff,
+        };
+        self.tt.insert_by(key, entry, answers);
+        self.cutoff |= outer_cutoff;
+        solutions
+    }
+
+    /// Appends the first helpmate within `max_depth` plies to `line`, if any.
+    fn first_line(&mut self, board: &Board, or_node: bool, max_depth: i32, line: &mut Vec<Move>) {
+        let moves = if or_node {
+            board.enumerate_check()
+        } else {
+            board.enumerate_check_avoidance()
+        };
+        for mv in moves {
+            let mut child = board.clone();
+            child.apply_move(mv);
+            if self.count(&child, !or_node, max_depth - 1) > 0 {
+                line.push(mv);
+                self.first_line(&child, !or_node, max_depth - 1, line);
+                return;
+            }
+        }
+    }
+}
+
+/// Searches the shortest helpmates by iterative deepening with depths 1, 3, 5, ... under the
+/// limits of `options`. The solutions are counted to tell whether the shortest one is unique.
+pub fn solve_helpmate(board: &Board, options: &SolveOptions) -> HelpmateReport {
+    let start = Instant::now();
+    let mut report = HelpmateReport {
+        result: SolveResult::NoMate,
+        solutions: 0,
+        stats: SolveStats::default(),
+    };
+    if let Err(reason) = check_position(board, options.defender_hand) {
+        report.result = SolveResult::InvalidPosition(reason);
+        return report;
+    }
+
+    let board = with_defender_hand(board, options.defender_hand);
+    let mut helpmate = Helpmate {
+        options,
+        tt: TranspositionTable::new(options.tt_memory),
+        nodes: 0,
+        root_depth: 0,
+        max_ply: 0,
+        stopped: None,
+        cutoff: false,
+    };
+    let mut depth = 1;
+    report.result = loop {
+        if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            let reason = StopReason::MaxDepth;
+            break SolveResult::Unknown { reason };
+        }
+        helpmate.cutoff = false;
+        helpmate.root_depth = depth;
+        let solutions = helpmate.count(&board, true, depth);
+        if let Some(reason) = helpmate.stopped {
+            break SolveResult::Unknown { reason };
+        }
+        report.stats.depth = depth;
+        if solutions > 0 {
+            let mut moves = vec![];
+            helpmate.first_line(&board, true, depth, &mut moves);
+            // the line may be searched again if the entries are replaced
+            if let Some(reason) = helpmate.stopped {
+                break SolveResult::Unknown { reason };
+            }
+            report.solutions = solutions;
+            break SolveResult::Mate {
+                length: moves.len(),
+                pieces_used: pieces_used(&board, &moves),
+                moves,
+            };
+        }
+        if !helpmate.cutoff {
+            break SolveResult::NoMate;
+        }
+        depth += 2;
+    };
+
+    report.stats.nodes = helpmate.nodes;
+    report.stats.max_depth = helpmate.max_ply;
+    report.stats.tt = helpmate.tt.stats();
+    report.stats.tt_entries = helpmate.tt.len();
+    report.stats.tt_capacity = helpmate.tt.capacity();
+    report.stats.elapsed = start.elapsed();
+    report
+}
+
+/// Returns the pieces in the first player's hand which `moves` drops, except the ones
+/// captured before they are dropped.
+fn pieces_used(board: &Board, moves: &[Move]) -> Vec<Piece> {
+    let mut used = [0; PIECE_TYPES - 1];
+    let mut after = board.clone();
+    for &mv in moves {
+        after.apply_move(mv);
+        for (i, used) in used.iter_mut().enumerate() {
+            let piece = Piece(i as i8);
+            *used = (*used).max(board.get_first_hand(piece) - after.get_first_hand(piece));
+        }
+    }
+    (0..(PIECE_TYPES - 1))
+        .flat_map(|i| (0..used[i]).map(move |_| Piece(i as i8)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{solve_with_options, DefenderHand, MudaaiRule};
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_helpmate() {
+        let options = SolveOptions {
+            max_depth: Some(9),
+            ..SolveOptions::default()
+        };
+
+        // ▲８二銀打 △９二玉 ▲９三金打, while the tsume has no mate
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_GOLD, 1);
+        board.set_first_hand(PIECE_SILVER, 1);
+        let report = solve_helpmate(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Mate {
+                moves: vec![
+                    Move::FromHand(P(1, 1), PIECE_SILVER.as_first()),
+                    Move::NoPromotion(P(0, 0), P(1, 0)),
+                    Move::FromHand(P(2, 0), PIECE_GOLD.as_first()),
+                ],
+                length: 3,
+                pieces_used: vec![PIECE_SILVER, PIECE_GOLD],
+            }
+        );
+        assert!(report.is_unique());
+        assert_eq!(report.stats.depth, 3);
+        assert!(report.stats.nodes > 0);
+        assert!(!matches!(
+            solve_with_options(&board, &options).result,
+            SolveResult::Mate { .. }
+        ));
+
+        // the rooks can be dropped in either order
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_ROOK, 2);
+        let report = solve_helpmate(&board, &options);
+        assert_eq!(report.result.moves().map(|m| m.len()), Some(3));
+        assert_eq!(report.solutions, 2);
+        assert!(!report.is_unique());
+
+        // ▲８四香打 △８三金打 ▲同香不成 △９一玉 ▲８二金打, through an interposition which is
+        // futile in tsume
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 1), PIECE_KING.as_second());
+        board.set_sided_piece(P(4, 1), PIECE_GOLD.as_first());
+        board.set_first_hand(PIECE_LANCE, 1);
+        board.set_second_hand(PIECE_GOLD, 1);
+        let options = SolveOptions {
+            defender_hand: DefenderHand::AsGiven,
+            mudaai: MudaaiRule::Unprotected,
+            ..options
+        };
+        let report = solve_helpmate(&board, &options);
+        assert_eq!(
+            report.result.moves(),
+            Some(
+                &[
+                    Move::FromHand(P(3, 1), PIECE_LANCE.as_first()),
+                    Move::FromHand(P(2, 1), PIECE_GOLD.as_second()),
+                    Move::NoPromotion(P(3, 1), P(2, 1)),
+                    Move::NoPromotion(P(0, 1), P(0, 0)),
+                    Move::FromHand(P(1, 1), PIECE_GOLD.as_first()),
+                ][..]
+            )
+        );
+        assert!(report.is_unique());
+        let options = SolveOptions {
+            defender_hand: DefenderHand::default(),
+            ..options
+        };
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        assert_eq!(solve_helpmate(&board, &options).result, SolveResult::NoMate);
+        board.set_first_hand(PIECE_PAWN, 2);
+        assert_eq!(
+            solve_helpmate(&board, &options).result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxDepth
+            }
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Brinkmate (hisshi) problems.
+//!
+//! A hisshi is a move of the first player, which is not a check, after which the first player
+//! mates against every move of the second player. The mates are searched by the tsume solver,
+//! so the first player's king, if any, is ignored and the second player can't check it.
+
+use super::tt::TranspositionTable;
+use super::{
+    check_position, deepen, with_defender_hand, DefenderHand, InvalidPosition, Iteration, Minimax,
+    SolveOptions, SolveResult, SolveStats, StopReason, ThreatmateReply,
+};
+use crate::board::Board;
+use crate::common::*;
+
+/// A move of the first player which is a hisshi.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HisshiKey {
+    pub mv: Move,
+    /// Every move of the second player after `mv`, with the mate after it.
+    pub replies: Vec<ThreatmateReply>,
+}
+
+/// A move of the first player which is not a hisshi.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HisshiRefutation {
+    pub mv: Move,
+    /// The first move of the second player after which no mate is found.
+    pub reply: Move,
+    /// `NoMate`, or `Unknown` if the mate is longer than `max_depth`.
+    pub result: SolveResult,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct HisshiReport {
+    /// The hisshi moves, in the order of `Board::enumerate_first_moves`.
+    pub keys: Vec<HisshiKey>,
+    /// The other moves of the first player which are not checks.
+    pub refutations: Vec<HisshiRefutation>,
+    /// Why the analysis stopped, if it didn't finish.
+    pub stopped: Option<StopReason>,
+}
+
+impl HisshiReport {
+    /// Returns the first hisshi move, if any.
+    pub fn key(&self) -> Option<Move> {
+        self.keys.first().map(|key| key.mv)
+    }
+
+    /// Whether the problem has exactly one hisshi move.
+    pub fn is_unique(&self) -> bool {
+        self.keys.len() == 1 && self.stopped.is_none()
+    }
+}
+
+/// Searches the hisshi moves in `board`, where the first player is to move.
+///
+/// `max_depth` limits each mate after the replies of the second player, and the other limits
+/// of `options` apply to the whole analysis. The transposition table is shared by all the
+/// mates, which are searched in a single thread.
+pub fn solve_hisshi(
+    board: &Board,
+    options: &SolveOptions,
+) -> Result<HisshiReport, InvalidPosition> {
+    check_position(board, options.defender_hand)?;
+    let mut report = HisshiReport {
+        keys: vec![],
+        refutations: vec![],
+        stopped: None,
+    };
+
+    let board = with_defender_hand(board, options.defender_hand);
+    let options = SolveOptions {
+        defender_hand: DefenderHand::AsGiven,
+        ..options.clone()
+    };
+    let mut tt = TranspositionTable::new(options.tt_memory);
+    let mut minimax = Minimax::new(&options, &mut tt);
+
+    for mv in board.enumerate_first_moves() {
+        let mut after = board.clone();
+        after.apply_move(mv);
+        if after.is_check() {
+            continue;
+        }
+        let mut replies = vec![];
+        let mut refutation = None;
+        for reply in after.enumerate_second_moves() {
+            let mut child = after.clone();
+            child.apply_move(reply);
+            let result = solve(&mut minimax, &child);
+            if let Some(reason) = minimax.stopped {
+                report.stopped = Some(reason);
+                return Ok(report);
+            }
+            if let SolveResult::Mate { .. } = result {
+                replies.push(ThreatmateReply { mv: reply, result });
+            } else {
+                refutation = Some(HisshiRefutation { mv, reply, result });
+                break;
+            }
+        }
+        match refutation {
+            Some(refutation) => report.refutations.push(refutation),
+            None => report.keys.push(HisshiKey { mv, replies }),
+        }
+    }
+    Ok(report)
+}
+
+/// Solves `board` by iterative deepening as `solve_with_options`, sharing the table of `minimax`.
+fn solve(minimax: &mut Minimax, board: &Board) -> SolveResult {
+    let options = minimax.options;
+    let (result, _) = deepen(options, &mut SolveStats::default(), 1, |depth| {
+        minimax.cutoff = false;
+        let line = minimax.search_line(board, depth);
+        Iteration {
+            line,
+            cutoff: minimax.cutoff,
+            stopped: minimax.stopped,
+            max_ply: minimax.max_ply,
+        }
+    });
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_hisshi() {
+        // after ▲８三金, the king has a single move and is mated after it
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(3, 1), PIECE_GOLD.as_first());
+        board.set_first_hand(PIECE_SILVER, 1);
+        let options = SolveOptions {
+            max_depth: Some(3),
+            defender_hand: DefenderHand::AsGiven,
+            ..SolveOptions::default()
+        };
+
+        let report = solve_hisshi(&board, &options).unwrap();
+        assert!(report.is_unique());
+        assert_eq!(report.key(), Some(Move::NoPromotion(P(3, 1), P(2, 1))));
+        let replies = &report.keys[0].replies;
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].mv.src(), Some(P(0, 0)));
+        assert!(matches!(replies[0].result, SolveResult::Mate { .. }));
+
+        // every other move which is not a check is refuted
+        let moves = board.enumerate_first_moves();
+        let checks = moves
+            .iter()
+            .filter(|&&mv| {
+                let mut board = board.clone();
+                board.apply_move(mv);
+                board.is_check()
+            })
+            .count();
+        assert_eq!(report.refutations.len(), moves.len() - checks - 1);
+        for refutation in &report.refutations {
+            assert!(!matches!(refutation.result, SolveResult::Mate { .. }));
+        }
+
+        let options = SolveOptions {
+            max_nodes: Some(10),
+            ..options
+        };
+        let report = solve_hisshi(&board, &options).unwrap();
+        assert_eq!(report.stopped, Some(StopReason::MaxNodes));
+
+        board.set_sided_piece(P(1, 0), PIECE_GOLD.as_first());
+        assert_eq!(
+            solve_hisshi(&board, &options),
+            Err(InvalidPosition::DefenderInCheck)
+        );
+    }
+}
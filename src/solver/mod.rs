@@ -0,0 +1,1475 @@
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+
+mod checkpoint;
+mod dfpn;
+mod hand;
+mod helpmate;
+mod hisshi;
+mod mudaai;
+mod options;
+mod ordering;
+mod parallel;
+mod proof;
+mod result;
+mod threatmate;
+pub mod tt;
+mod yozume;
+
+pub use checkpoint::{resume, Checkpoint, CheckpointError};
+pub use dfpn::{solve_dfpn, solve_dfpn_with_options, solve_dfpn_with_table, DfpnEntry, DfpnError};
+use hand::Hands;
+pub use helpmate::{solve_helpmate, HelpmateReport};
+pub use hisshi::{solve_hisshi, HisshiKey, HisshiRefutation, HisshiReport};
+pub use mudaai::MudaaiRule;
+use options::CHECK_INTERVAL;
+pub use options::{
+    CancelHandle, CheckpointOptions, DefenderHand, Progress, ProgressObserver, SolveOptions,
+    StopReason,
+};
+use ordering::OrderingTables;
+pub use ordering::{EvasionOrder, MoveOrdering, OrderingStats};
+pub use proof::{solve_proof_tree, ProofError, ProofNode, ProofTree};
+pub use result::{InvalidPosition, SolveReport, SolveResult, SolveStats};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+pub use threatmate::{analyze_threatmate, solve_threatmate, ThreatmateReply, ThreatmateReport};
+use tt::{ShardedTranspositionTable, TTAccess, TTValue, TranspositionTable, AND_NODE_SALT};
+pub use yozume::{analyze_yozume, Alternative, YozumeReport};
+
+/// A mating line found by `solve`. Only its first move is kept, and the rest of the moves are
+/// followed in the transposition table by `Minimax::main_line`, so that the entries of the table
+/// don't own memory on the heap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Line {
+    /// The first move, or `None` if the second player is mated already.
+    first: Option<Move>,
+    /// Number of moves.
+    length: usize,
+    /// Number of pieces left in the first player's hand at the end of the line.
+    surplus: i32,
+    /// Bounds of the hands with which the mate holds against every defence (proof pieces).
+    pieces: Hands,
+}
+
+impl Line {
+    /// Returns the line of no moves, where the second player is mated.
+    fn mated(surplus: i32, pieces: Hands) -> Line {
+        Line {
+            first: None,
+            length: 0,
+            surplus,
+            pieces,
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.length
+    }
+
+    /// Puts `mv` before the moves of the line.
+    fn prepend(&mut self, mv: Move) {
+        self.first = Some(mv);
+        self.length += 1;
+    }
+
+    /// Whether the second player prefers this line to `other`, which is the same as it is
+    /// longer, or it has the same length and leaves fewer surplus pieces to the first player.
+    fn is_better_defence(&self, other: &Line) -> bool {
+        (self.len(), -self.surplus) > (other.len(), -other.surplus)
+    }
+}
+
+/// Result of the search of a node by `solve`, stored in the transposition table.
+#[derive(Clone, Debug)]
+pub struct MinimaxEntry {
+    hands: Hands,
+    /// The depth searched, or `i32::MAX` if the result doesn't depend on the depth.
+    max_depth: i32,
+    /// The mate found within `max_depth`.
+    result: Option<Line>,
+}
+
+impl MinimaxEntry {
+    /// Returns the result of the search with `max_depth` if it is known from this entry.
+    /// A mate is also the shortest one for deeper searches, and no mate also means
+    /// no mate for shallower searches.
+    fn result_for(&self, max_depth: i32) -> Option<Option<Line>> {
+        match &self.result {
+            Some(line) if line.len() as i32 <= max_depth => Some(Some(*line)),
+            None if max_depth <= self.max_depth => Some(None),
+            _ => None,
+        }
+    }
+
+    /// Whether `result_for` is known for the position with `hands`. Besides the entry for
+    /// the same hands, a mate also holds with hands dominating its proof pieces, and no mate
+    /// with hands better for the first player also means no mate. The mate may be shorter with
+    /// other hands, so it is taken only for the same hands if `exact`.
+    fn answers(&self, hands: Hands, max_depth: i32, exact: bool) -> bool {
+        match &self.result {
+            Some(line) => {
+                (self.hands == hands || (!exact && hands.dominates(&line.pieces)))
+                    && line.len() as i32 <= max_depth
+            }
+            None => max_depth <= self.max_depth && self.hands.dominates(&hands),
+        }
+    }
+}
+
+impl TTValue for MinimaxEntry {
+    fn priority(&self) -> u64 {
+        self.max_depth as u64
+    }
+}
+
+/// Solves the tsume shogi problem within `max_depth`, which must be odd.
+/// The second player is assumed to have all the remaining pieces.
+/// Returns the shortest mate if any, and `None` otherwise, including when the position is invalid.
+pub fn solve(board: &Board, max_depth: i32) -> Option<Vec<Move>> {
+    solve_with_table(board, max_depth, &mut TranspositionTable::default())
+}
+
+/// Same as `solve`, but uses the given transposition table.
+pub fn solve_with_table(
+    board: &Board,
+    max_depth: i32,
+    tt: &mut TranspositionTable<MinimaxEntry>,
+) -> Option<Vec<Move>> {
+    assert!(max_depth % 2 == 1);
+    // the hashes of the position would index out of range with invalid hands
+    check_position(board, DefenderHand::AllRemaining).ok()?;
+
+    let board = with_all_remaining_pieces(board);
+    let options = SolveOptions::default();
+    Minimax::new(&options, tt).search(&board, max_depth)
+}
+
+/// Solves the problem by iterative deepening with depths 1, 3, 5, ... under the limits of `options`.
+pub fn solve_with_options(board: &Board, options: &SolveOptions) -> SolveReport {
+    solve_from(board, options, None)
+}
+
+/// Same as `solve_with_options`, but starts from `checkpoint` if any.
+fn solve_from(
+    board: &Board,
+    options: &SolveOptions,
+    checkpoint: Option<&Checkpoint>,
+) -> SolveReport {
+    let start = Instant::now();
+    let mut stats = SolveStats::default();
+    if let Err(reason) = check_position(board, options.defender_hand) {
+        return SolveReport {
+            result: SolveResult::InvalidPosition(reason),
+            stats,
+        };
+    }
+
+    let board = with_defender_hand(board, options.defender_hand);
+    let saved = CheckpointState::new(board.hash(), start);
+    let progress = ProgressState::new(start);
+    let (depth, nodes, entries) = match checkpoint {
+        Some(checkpoint) => (checkpoint.depth, checkpoint.nodes, checkpoint.entries()),
+        None => (1, 0, &[][..]),
+    };
+    // the last depth searched completely: the iterations before the resumed one were completed
+    // before the checkpoint was saved, whose nodes are counted too, and none is completed otherwise
+    stats.depth = match checkpoint {
+        Some(checkpoint) if checkpoint.depth > 1 => checkpoint.depth - 2,
+        _ => 0,
+    };
+    let result = if options.threads > 1 {
+        let tt = ShardedTranspositionTable::new(options.tt_memory, options.threads * 4);
+        for (key, entry) in entries {
+            tt.insert_by(*key, entry.clone(), |e| e.hands == entry.hands);
+        }
+        let shared = SharedState {
+            nodes: AtomicU64::new(nodes),
+            ..SharedState::default()
+        };
+        let mut max_ply = 0;
+        let (result, depth) = deepen(options, &mut stats, depth, |depth| {
+            let iteration =
+                parallel::search(&board, depth, options, &tt, &shared, &progress, &saved);
+            max_ply = max_ply.max(iteration.max_ply);
+            iteration
+        });
+        stats.nodes = shared.nodes.load(Ordering::Relaxed);
+        stats.ordering = *shared.ordering.lock().unwrap();
+        stats.max_depth = max_ply;
+        stats.tt = tt.stats();
+        stats.tt_entries = tt.len();
+        stats.tt_capacity = tt.capacity();
+        saved.save_at_stop(options, &result, depth, stats.nodes, || tt.entries());
+        result
+    } else {
+        let mut tt = TranspositionTable::new(options.tt_memory);
+        for (key, entry) in entries {
+            tt.insert_by(*key, entry.clone(), |e| e.hands == entry.hands);
+        }
+        let mut minimax = Minimax::new(options, &mut tt);
+        minimax.progress = Some(&progress);
+        minimax.checkpoint = Some(&saved);
+        minimax.nodes = nodes;
+        let (result, depth) = deepen(options, &mut stats, depth, |depth| {
+            minimax.cutoff = false;
+            let line = minimax.search_line(&board, depth);
+            Iteration {
+                line,
+                cutoff: minimax.cutoff,
+                stopped: minimax.stopped,
+                max_ply: minimax.max_ply,
+            }
+        });
+        stats.nodes = minimax.nodes;
+        stats.ordering = minimax.ordering_stats;
+        stats.max_depth = minimax.max_ply;
+        stats.tt = tt.stats();
+        stats.tt_entries = tt.len();
+        stats.tt_capacity = tt.capacity();
+        saved.save_at_stop(options, &result, depth, stats.nodes, || {
+            TTAccess::entries(&tt)
+        });
+        result
+    };
+
+    stats.elapsed = start.elapsed();
+    SolveReport { result, stats }
+}
+
+/// Result of an iteration of iterative deepening.
+struct Iteration {
+    /// The main line of the shortest mate found, and its proof pieces.
+    line: Option<(Vec<Move>, Hands)>,
+    cutoff: bool,
+    stopped: Option<StopReason>,
+    max_ply: i32,
+}
+
+/// Runs `search` with depths `depth`, `depth + 2`, ... until the answer is found or the search
+/// stops. Returns the result and the depth of the last iteration.
+fn deepen(
+    options: &SolveOptions,
+    stats: &mut SolveStats,
+    mut depth: i32,
+    mut search: impl FnMut(i32) -> Iteration,
+) -> (SolveResult, i32) {
+    loop {
+        if options.max_depth.is_some_and(|max_depth| depth > max_depth) {
+            let reason = StopReason::MaxDepth;
+            return (SolveResult::Unknown { reason }, depth);
+        }
+        let iteration = search(depth);
+        if let Some(reason) = iteration.stopped {
+            return (SolveResult::Unknown { reason }, depth);
+        }
+        stats.depth = depth;
+        match iteration.line {
+            Some((moves, pieces)) => {
+                let pieces_used = pieces.first_pieces();
+                let result = SolveResult::Mate {
+                    length: moves.len(),
+                    moves,
+                    pieces_used,
+                };
+                return (result, depth);
+            }
+            None if !iteration.cutoff => return (SolveResult::NoMate, depth),
+            None => depth += 2,
+        }
+    }
+}
+
+/// Checks that `board` is a valid problem, where the second player's hand is given by `hand`.
+fn check_position(board: &Board, hand: DefenderHand) -> Result<(), InvalidPosition> {
+    // the hands are checked first, since the hashes of the position depend on them
+    for i in 0..PIECE_TYPES {
+        let piece = Piece(i as i8);
+        let second = match hand {
+            DefenderHand::AsGiven => board.get_second_hand(piece),
+            _ if piece == PIECE_KING => board.get_second_hand(piece),
+            _ => 0,
+        };
+        let first = board.get_first_hand(piece);
+        if first < 0 || second < 0 {
+            return Err(InvalidPosition::NegativeHand(piece));
+        }
+        if piece == PIECE_KING && first + second > 0 {
+            return Err(InvalidPosition::TooManyPieces(piece));
+        }
+    }
+    // the second player's king is counted with the other pieces
+    let mut piece_counts = [1; PIECE_TYPES];
+    for (count, &n) in piece_counts.iter_mut().zip(&PIECE_COUNTS) {
+        *count = n as i32;
+    }
+    for i in 0..(PIECE_TYPES - 1) {
+        piece_counts[i] -= board.get_first_hand(Piece(i as i8)) as i32;
+        piece_counts[i] -= match hand {
+            DefenderHand::AllRemaining => 0,
+            DefenderHand::AsGiven => board.get_second_hand(Piece(i as i8)) as i32,
+            DefenderHand::Explicit(counts) => counts[i] as i32,
+        };
+    }
+    for y in 0..BOARD_SIZE {
+        for x in 0..BOARD_SIZE {
+            let piece = board.get_sided_piece(P(y, x));
+            if !piece.is_empty() && (piece.is_second() || piece.to_piece() != PIECE_KING) {
+                piece_counts[piece.to_piece().capture().0 as usize] -= 1;
+            }
+        }
+    }
+    if let Some(i) = piece_counts.iter().position(|&n| n < 0) {
+        return Err(InvalidPosition::TooManyPieces(Piece(i as i8)));
+    }
+    if board.find_second_king().is_none() {
+        return Err(InvalidPosition::NoDefenderKing);
+    }
+    if board.is_check() {
+        return Err(InvalidPosition::DefenderInCheck);
+    }
+    Ok(())
+}
+
+/// Returns a copy of `board` where the second player's hand is given by `hand`.
+fn with_defender_hand(board: &Board, hand: DefenderHand) -> Board {
+    match hand {
+        DefenderHand::AllRemaining => with_all_remaining_pieces(board),
+        DefenderHand::AsGiven => board.clone(),
+        DefenderHand::Explicit(counts) => {
+            let mut board = board.clone();
+            for (i, &count) in counts.iter().enumerate() {
+                board.set_second_hand(Piece(i as i8), count as i8);
+            }
+            board
+        }
+    }
+}
+
+/// Returns a copy of `board` where the second player has all pieces
+/// which are neither on the board nor in the first player's hand.
+#[allow(clippy::needless_range_loop)]
+fn with_all_remaining_pieces(board: &Board) -> Board {
+    let mut board = board.clone();
+
+    let mut piece_counts = PIECE_COUNTS;
+    for i in 0..(PIECE_TYPES - 1) {
+        piece_counts[i] -= board.get_first_hand(Piece(i as i8));
+    }
+    for y in 0..BOARD_SIZE {
+        for x in 0..BOARD_SIZE {
+            let piece = board.get_sided_piece(P(y, x));
+            if !piece.is_empty() {
+                let piece = piece.to_piece().capture();
+                if piece != PIECE_KING {
+                    piece_counts[piece.0 as usize] -= 1;
+                }
+            }
+        }
+    }
+    for i in 0..(PIECE_TYPES - 1) {
+        board.set_second_hand(Piece(i as i8), piece_counts[i]);
+    }
+    board
+}
+
+pub fn intermediate_boards(board: &Board, steps: &Vec<Move>) -> Vec<Board> {
+    let mut board = board.clone();
+    let mut ret = vec![board.clone()];
+    for &mv in steps {
+        board.apply_move(mv);
+        ret.push(board.clone());
+    }
+    ret
+}
+
+/// Number of pieces in the first player's hand.
+fn surplus(board: &Board) -> i32 {
+    (0..(PIECE_TYPES - 1))
+        .map(|i| board.get_first_hand(Piece(i as i8)) as i32)
+        .sum()
+}
+
+/// State shared among the threads of a parallel search.
+#[derive(Default)]
+struct SharedState {
+    nodes: AtomicU64,
+    /// Set when a thread stops, so that the other threads stop too.
+    has_stopped: AtomicBool,
+    stopped: Mutex<Option<StopReason>>,
+    ordering: Mutex<OrderingStats>,
+}
+
+/// State of the progress reports of a search, shared among the threads.
+struct ProgressState {
+    start: Instant,
+    /// When the observer was last called, and the number of nodes then.
+    last: Mutex<(Instant, u64)>,
+    /// The shortest mate found at the root in the current iteration.
+    best_line: Mutex<Option<Vec<Move>>>,
+}
+
+impl ProgressState {
+    fn new(start: Instant) -> ProgressState {
+        ProgressState {
+            start,
+            last: Mutex::new((start, 0)),
+            best_line: Mutex::new(None),
+        }
+    }
+
+    /// Whether a mate of `length` at the root is shorter than the best one recorded.
+    fn improves(&self, length: usize) -> bool {
+        self.best_line
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map_or(true, |best| length < best.len())
+    }
+
+    /// Records the mate of `moves` at the root if it is shorter than the best one.
+    fn offer(&self, moves: Vec<Move>) {
+        let mut best_line = self.best_line.lock().unwrap();
+        if best_line
+            .as_ref()
+            .map_or(true, |best| moves.len() < best.len())
+        {
+            *best_line = Some(moves);
+        }
+    }
+}
+
+/// State of the checkpoints of a search, shared among the threads.
+struct CheckpointState {
+    /// Hash of the position searched.
+    position: u64,
+    /// When the last checkpoint was saved.
+    last: Mutex<Instant>,
+}
+
+impl CheckpointState {
+    fn new(position: u64, start: Instant) -> CheckpointState {
+        CheckpointState {
+            position,
+            last: Mutex::new(start),
+        }
+    }
+
+    /// Saves a checkpoint if checkpoints are enabled and `result` is unknown,
+    /// so that the iteration with `depth` can be resumed.
+    fn save_at_stop(
+        &self,
+        options: &SolveOptions,
+        result: &SolveResult,
+        depth: i32,
+        nodes: u64,
+        entries: impl FnOnce() -> Vec<(u64, MinimaxEntry)>,
+    ) {
+        if let (Some(checkpoint), SolveResult::Unknown { .. }) = (&options.checkpoint, result) {
+            let saved = Checkpoint::new(self.position, depth, nodes, options, entries());
+            // a failure keeps the previous checkpoint
+            let _ = saved.save(&checkpoint.path);
+        }
+    }
+}
+
+/// Depth-limited minimax search.
+struct Minimax<'a> {
+    options: &'a SolveOptions,
+    tt: &'a mut dyn TTAccess<MinimaxEntry>,
+    /// State shared with other threads searching in parallel.
+    shared: Option<&'a SharedState>,
+    /// State of the progress reports, if they are enabled.
+    progress: Option<&'a ProgressState>,
+    /// State of the checkpoints, if they are enabled.
+    checkpoint: Option<&'a CheckpointState>,
+    /// Number of nodes searched by this thread.
+    nodes: u64,
+    /// Depth of the current iteration.
+    root_depth: i32,
+    /// The largest number of plies from the root among the nodes visited.
+    max_ply: i32,
+    stopped: Option<StopReason>,
+    /// Whether some line was cut off by the depth limit.
+    cutoff: bool,
+    /// Whether the mates are taken from the table only for the same hands, so that their
+    /// lengths are exact.
+    exact: bool,
+    ordering: OrderingTables,
+    ordering_stats: OrderingStats,
+}
+
+impl<'a> Minimax<'a> {
+    fn new(options: &'a SolveOptions, tt: &'a mut dyn TTAccess<MinimaxEntry>) -> Minimax<'a> {
+        Minimax {
+            options,
+            tt,
+            shared: None,
+            progress: None,
+            checkpoint: None,
+            nodes: 0,
+            root_depth: 0,
+            max_ply: 0,
+            stopped: None,
+            cutoff: false,
+            exact: false,
+            ordering: OrderingTables::default(),
+            ordering_stats: OrderingStats::default(),
+        }
+    }
+
+    fn search(&mut self, board: &Board, max_depth: i32) -> Option<Vec<Move>> {
+        self.search_line(board, max_depth).map(|(moves, _)| moves)
+    }
+
+    /// Returns the main line of the shortest mate within `max_depth` and its proof pieces.
+    fn search_line(&mut self, board: &Board, max_depth: i32) -> Option<(Vec<Move>, Hands)> {
+        self.root_depth = max_depth;
+        if let Some(progress) = self.progress {
+            *progress.best_line.lock().unwrap() = None;
+        }
+        let line = self.solve_first(board, max_depth)?;
+        let moves = self.main_line(board, true, &line)?;
+        Some((moves, line.pieces))
+    }
+
+    /// Returns the moves of `line` found at `board`, which is an OR node if `or_node`.
+    /// The moves after the first one are followed in the table, where the nodes whose entries
+    /// have been replaced are searched again. Returns `None` if the search stops.
+    fn main_line(&mut self, board: &Board, or_node: bool, line: &Line) -> Option<Vec<Move>> {
+        let cutoff = self.cutoff;
+        // the longest defences are chosen by the exact lengths
+        let exact = std::mem::replace(&mut self.exact, true);
+        let mut board = board.clone();
+        let mut or_node = or_node;
+        let mut line = *line;
+        let mut moves = vec![];
+        while let Some(mv) = line.first {
+            moves.push(mv);
+            board.apply_move(mv);
+            or_node = !or_node;
+            // the rest of the line is the answer of the child within its length
+            let depth = line.len() as i32 - 1;
+            let next = if or_node {
+                self.solve_first(&board, depth)
+            } else {
+                self.solve_second(&board, depth)
+            };
+            match next {
+                Some(next) => line = next,
+                None => {
+                    self.cutoff = cutoff;
+                    self.exact = exact;
+                    return None;
+                }
+            }
+        }
+        self.cutoff = cutoff;
+        self.exact = exact;
+        Some(moves)
+    }
+
+    /// Offers the mate starting with `mv` at the root and followed by `line` to the progress
+    /// reports, if it is shorter than the best one.
+    fn offer_progress(&mut self, board: &Board, mv: Move, line: &Line) {
+        let progress = match self.progress {
+            Some(progress) if progress.improves(line.len() + 1) => progress,
+            _ => return,
+        };
+        let mut child = board.clone();
+        child.apply_move(mv);
+        if let Some(rest) = self.main_line(&child, false, line) {
+            progress.offer(std::iter::once(mv).chain(rest).collect());
+        }
+    }
+
+    /// Counts the node and returns whether the search should stop.
+    fn visit(&mut self, max_depth: i32) -> bool {
+        if self.stopped.is_some() {
+            return true;
+        }
+        self.nodes += 1;
+        self.max_ply = self.max_ply.max(self.root_depth - max_depth);
+        let nodes = match self.shared {
+            None => {
+                self.stopped = self.options.check_stop(self.nodes);
+                self.nodes
+            }
+            Some(shared) => {
+                if shared.has_stopped.load(Ordering::Acquire) {
+                    self.stopped = *shared.stopped.lock().unwrap();
+                    return true;
+                }
+                let nodes = shared.nodes.fetch_add(1, Ordering::Relaxed) + 1;
+                self.stopped = self.options.check_stop(nodes);
+                if let Some(reason) = self.stopped {
+                    shared.stopped.lock().unwrap().get_or_insert(reason);
+                    shared.has_stopped.store(true, Ordering::Release);
+                }
+                nodes
+            }
+        };
+        if nodes % CHECK_INTERVAL == 0 {
+            self.report_progress(nodes);
+            self.save_checkpoint(nodes);
+        }
+        self.stopped.is_some()
+    }
+
+    /// Calls the progress observer if its interval has passed since the last call.
+    fn report_progress(&mut self, nodes: u64) {
+        let (observer, progress) = match (&self.options.progress, self.progress) {
+            (Some(observer), Some(progress)) => (observer, progress),
+            _ => return,
+        };
+        let now = Instant::now();
+        let mut last = progress.last.lock().unwrap();
+        // another thread may have reported more nodes
+        if now.duration_since(last.0) < observer.interval || nodes <= last.1 {
+            return;
+        }
+        *last = (now, nodes);
+        observer.notify(&Progress {
+            nodes,
+            depth: self.root_depth,
+            best_line: progress.best_line.lock().unwrap().clone(),
+            elapsed: now.duration_since(progress.start),
+            tt_fill: self.tt.fill(),
+        });
+    }
+
+    /// Saves a checkpoint if its interval has passed since the last one.
+    fn save_checkpoint(&mut self, nodes: u64) {
+        let (options, state) = match (&self.options.checkpoint, self.checkpoint) {
+            (Some(options), Some(state)) => (options, state),
+            _ => return,
+        };
+        let now = Instant::now();
+        let mut last = state.last.lock().unwrap();
+        if now.duration_since(*last) < options.interval {
+            return;
+        }
+        *last = now;
+        let checkpoint = Checkpoint::new(
+            state.position,
+            self.root_depth,
+            nodes,
+            self.options,
+            self.tt.entries(),
+        );
+        // a failure keeps the previous checkpoint
+        let _ = checkpoint.save(&options.path);
+    }
+
+    /// Stores the result unless the search is stopped, in which case the result is incomplete.
+    fn store(
+        &mut self,
+        key: u64,
+        hands: Hands,
+        max_depth: i32,
+        cutoff: bool,
+        result: &Option<Line>,
+    ) {
+        if self.stopped.is_some() {
+            return;
+        }
+        let max_depth = if result.is_none() && !cutoff {
+            i32::MAX
+        } else {
+            max_depth
+        };
+        self.tt.store(
+            key,
+            MinimaxEntry {
+                hands,
+                max_depth,
+                result: *result,
+            },
+            &mut |e| e.hands == hands,
+        );
+    }
+
+    /// Number of plies from the root to a node with `max_depth`.
+    fn ply(&self, max_depth: i32) -> usize {
+        (self.root_depth - max_depth).max(0) as usize
+    }
+
+    /// Looks up the result in the table, marking a cutoff if the result depends on the depth.
+    fn lookup(&mut self, key: u64, hands: Hands, max_depth: i32) -> Option<Option<Line>> {
+        let exact = self.exact;
+        let entry = self
+            .tt
+            .probe(key, &mut |e| e.answers(hands, max_depth, exact))?;
+        let res = entry.result_for(max_depth)?;
+        if res.is_none() && entry.max_depth != i32::MAX {
+            self.cutoff = true;
+        }
+        Some(res)
+    }
+
+    /// Returns the shortest mate within `max_depth`. The first one is taken among the shortest ones.
+    fn solve_first(&mut self, board: &Board, max_depth: i32) -> Option<Line> {
+        if self.visit(max_depth) {
+            return None;
+        }
+        let key = board.board_hash();
+        let hands = Hands::of(board);
+        if let Some(res) = self.lookup(key, hands, max_depth) {
+            return res;
+        }
+        let outer_cutoff = std::mem::replace(&mut self.cutoff, false);
+
+        let moves = board.enumerate_check();
+
+        let mut shortest: Option<Line> = None;
+
+        if max_depth == 1 {
+            // the children of a leaf are judged without visiting them, but they count as searched
+            if !moves.is_empty() {
+                self.max_ply = self.max_ply.max(self.root_depth);
+            }
+            match self.options.mudaai.find_mate_in_1(board, &moves) {
+                Some(mv) => {
+                    let mut child = board.clone();
+                    child.apply_move(mv);
+                    let pieces = self.options.mudaai.mated_proof(&child);
+                    let mut line = Line::mated(surplus(&child), pieces.before(board, mv));
+                    line.prepend(mv);
+                    shortest = Some(line);
+                }
+                None => self.cutoff |= !moves.is_empty(),
+            }
+        } else if max_depth == 3 && self.options.mudaai == MudaaiRule::Disabled {
+            // the mate within the depth is judged without visiting the children
+            if !moves.is_empty() {
+                self.max_ply = self.max_ply.max(self.root_depth);
+            }
+            match board.find_mate_in_3(&moves) {
+                Some(mv) => shortest = Some(self.mate_in_3_line(board, mv)),
+                // a deeper search may mate, which isn't ruled out to keep the leaves cheap
+                None => self.cutoff |= !moves.is_empty(),
+            }
+        } else {
+            let ply = self.ply(max_depth);
+            let order = self
+                .ordering
+                .order(&self.options.ordering, board, &moves, true, ply);
+            // index of the shortest mate in `moves`
+            let mut best = 0;
+            for &i in &order {
+                // only a shorter mate, or one of the same length generated earlier, is searched
+                let child_depth = match &shortest {
+                    None => max_depth - 1,
+                    Some(line) if i < best => line.len() as i32 - 1,
+                    Some(line) => line.len() as i32 - 3,
+                };
+                if child_depth < 0 {
+                    continue;
+                }
+                let mv = moves[i];
+                let mut child = board.clone();
+                child.apply_move(mv);
+
+                if let Some(mut line) = self.solve_second(&child, child_depth) {
+                    if ply == 0 {
+                        self.offer_progress(board, mv, &line);
+                    }
+                    line.prepend(mv);
+                    line.pieces = line.pieces.before(board, mv);
+                    shortest = Some(line);
+                    best = i;
+                }
+                if self.stopped.is_some() {
+                    return None;
+                }
+            }
+            if shortest.is_some() {
+                // the mate doesn't depend on the lines cut off by the shortened depths
+                self.cutoff = false;
+                let mv = moves[best];
+                self.ordering
+                    .record(&self.options.ordering, board, mv, true, ply, max_depth);
+                self.ordering_stats.mates += 1;
+                if order[0] == best {
+                    self.ordering_stats.first_mates += 1;
+                }
+            }
+        }
+
+        self.store(key, hands, max_depth, self.cutoff, &shortest);
+        self.cutoff |= outer_cutoff;
+        shortest
+    }
+
+    /// Returns the line of the mate within 3 plies starting with `mv`, which is found by
+    /// `Board::find_mate_in_3`. The defences and the replies are chosen as `solve_second` and
+    /// `solve_first` would choose them, but without visiting the nodes.
+    fn mate_in_3_line(&self, board: &Board, mv: Move) -> Line {
+        let mudaai = self.options.mudaai;
+        let mut child = board.clone();
+        child.apply_move(mv);
+
+        let mut longest: Option<Line> = None;
+        let mut pieces = vec![];
+        for evasion in child.enumerate_check_avoidance() {
+            let mut grandchild = child.clone();
+            grandchild.apply_move(evasion);
+            let reply = mudaai
+                .find_mate_in_1(&grandchild, &grandchild.enumerate_check())
+                .expect("every evasion is answered by a mate in 1");
+            let mut mated = grandchild.clone();
+            mated.apply_move(reply);
+            let mut line = Line::mated(
+                surplus(&mated),
+                mudaai.mated_proof(&mated).before(&grandchild, reply),
+            );
+            line.prepend(reply);
+            pieces.push(line.pieces.before(&child, evasion));
+            line.prepend(evasion);
+            // ties are broken by the order of the move generator
+            if longest.map_or(true, |longest| line.is_better_defence(&longest)) {
+                longest = Some(line);
+            }
+        }
+        let mut line = longest.unwrap_or_else(|| Line::mated(surplus(&child), Hands::of(&child)));
+        line.pieces = Hands::proof(&child, false, &pieces);
+        line.prepend(mv);
+        line.pieces = line.pieces.before(board, mv);
+        line
+    }
+
+    /// Returns the longest defence against which the first player mates within `max_depth`.
+    /// Among the longest ones, the first one leaving the fewest surplus pieces is taken.
+    ///
+    /// The candidates of futile interpositions are searched after the other defences.
+    /// A candidate is futile if the capture of the interposed piece mates within the length
+    /// of the longest of the other defences, and then it is neither a defence nor a refutation.
+    fn solve_second(&mut self, board: &Board, max_depth: i32) -> Option<Line> {
+        if self.visit(max_depth) {
+            return None;
+        }
+        let (moves, captures) = self.options.mudaai.split_evasions(board);
+
+        if moves.is_empty() {
+            return Some(Line::mated(surplus(board), Hands::proof(board, false, &[])));
+        }
+        if max_depth == 0 && captures.iter().any(|c| c.is_empty()) {
+            self.cutoff = true;
+            return None;
+        }
+
+        let key = board.board_hash() ^ AND_NODE_SALT;
+        let hands = Hands::of(board);
+        if let Some(res) = self.lookup(key, hands, max_depth) {
+            return res;
+        }
+        let outer_cutoff = std::mem::replace(&mut self.cutoff, false);
+
+        let mut longest: Option<Line> = None;
+        let mut refuted = false;
+        let mut pieces = vec![];
+        let ply = self.ply(max_depth);
+        let order = self
+            .ordering
+            .order(&self.options.ordering, board, &moves, false, ply);
+        // the candidates are searched after the other defences
+        let order = order
+            .iter()
+            .filter(|&&i| captures[i].is_empty())
+            .chain(order.iter().filter(|&&i| !captures[i].is_empty()))
+            .copied()
+            .collect::<Vec<_>>();
+        // index of the longest defence in `moves`
+        let mut best = 0;
+        // length of the mate against the defences other than the candidates
+        let mut other_length = None;
+
+        for (n, &i) in order.iter().enumerate() {
+            let mv = moves[i];
+            let mut child = board.clone();
+            child.apply_move(mv);
+
+            if !captures[i].is_empty() {
+                let length = *other_length
+                    .get_or_insert_with(|| longest.as_ref().map_or(0, |l| l.len() as i32));
+                let futile = self.futile_proof(board, mv, &captures[i], length);
+                if self.stopped.is_some() {
+                    return None;
+                }
+                if let Some(proof) = futile {
+                    pieces.push(proof);
+                    continue;
+                }
+                if max_depth == 0 {
+                    self.cutoff = true;
+                    refuted = true;
+                    break;
+                }
+            }
+
+            match self.solve_first(&child, max_depth - 1) {
+                None => {
+                    refuted = true;
+                    if self.stopped.is_none() {
+                        self.ordering.record(
+                            &self.options.ordering,
+                            board,
+                            mv,
+                            false,
+                            ply,
+                            max_depth,
+                        );
+                        self.ordering_stats.refutations += 1;
+                        if n == 0 {
+                            self.ordering_stats.first_refutations += 1;
+                        }
+                    }
+                    break;
+                }
+                Some(mut line) => {
+                    pieces.push(line.pieces.before(board, mv));
+                    line.prepend(mv);
+                    // ties are broken by the order of the move generator
+                    let update = match &longest {
+                        None => true,
+                        Some(line2) => {
+                            line.is_better_defence(line2)
+                                || (i < best && !line2.is_better_defence(&line))
+                        }
+                    };
+                    if update {
+                        longest = Some(line);
+                        best = i;
+                    }
+                }
+            }
+        }
+        if self.stopped.is_some() {
+            return None;
+        }
+        let longest = if refuted {
+            None
+        } else {
+            // every move may be a futile interposition, and then the second player is mated here
+            let mut line = longest.unwrap_or_else(|| Line::mated(surplus(board), hands));
+            line.pieces = Hands::proof(board, false, &pieces);
+            Some(line)
+        };
+
+        self.store(key, hands, max_depth, self.cutoff, &longest);
+        self.cutoff |= outer_cutoff;
+        longest
+    }
+
+    /// Returns the proof pieces if the interposition `mv` on `board` is futile: one of its
+    /// `captures` mates within `length`. The judgement doesn't depend on the depth of the node.
+    fn futile_proof(
+        &mut self,
+        board: &Board,
+        mv: Move,
+        captures: &[Move],
+        length: i32,
+    ) -> Option<Hands> {
+        let cutoff = self.cutoff;
+        let mut ret = None;
+        for &capture in captures {
+            let captured = mudaai::after_capture(board, mv, capture);
+            if let Some(line) = self.solve_second(&captured, length) {
+                let mut child = board.clone();
+                child.apply_move(mv);
+                ret = Some(line.pieces.before(&child, capture).before(board, mv));
+                break;
+            }
+            if self.stopped.is_some() {
+                break;
+            }
+        }
+        self.cutoff = cutoff;
+        ret
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::P;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[test]
+    fn test_problem() {
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+            board.set_sided_piece(P(0, 3), PIECE_PAWN.as_second());
+            board.set_sided_piece(P(0, 5), PIECE_PAWN.as_second());
+            board.set_sided_piece(P(1, 3), PIECE_KNIGHT.as_second());
+            board.set_sided_piece(P(1, 4), PIECE_KNIGHT.as_second());
+            board.set_sided_piece(P(1, 5), PIECE_KNIGHT.as_second());
+            board.set_sided_piece(P(4, 6), PIECE_KNIGHT.as_first());
+
+            let sol = solve(&board, 1);
+            assert!(sol.is_some() && sol.unwrap().len() == 1);
+        }
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+            board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+            board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+
+            let sol = solve(&board, 3);
+            assert!(sol.is_some() && sol.unwrap().len() == 3);
+        }
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+            board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+            board.set_sided_piece(P(7, 2), PIECE_GOLD.as_first());
+
+            let sol = solve(&board, 1);
+            assert!(sol.is_some() && sol.unwrap().len() == 1);
+        }
+        {
+            let mut board = Board::new();
+            board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+            board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+            board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+
+            let sol = solve(&board, 5);
+            assert!(sol.is_some() && sol.unwrap().len() == 3);
+        }
+    }
+
+    #[test]
+    fn test_surplus_tie_break() {
+        // after ▲４三銀打, both △５一玉 and △４一玉 are mated in one more move,
+        // but △５一玉 leaves a silver in the first player's hand
+        let mut board = Board::new();
+        board.set_sided_piece(P(1, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 2), PIECE_BISHOP.as_first());
+        board.set_sided_piece(P(2, 3), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_SILVER, 2);
+
+        let sol = solve(&board, 3).unwrap();
+        assert_eq!(sol.len(), 3);
+        assert_eq!(sol[0], Move::FromHand(P(2, 5), PIECE_SILVER.as_first()));
+        assert_eq!(sol[1], Move::NoPromotion(P(1, 4), P(0, 5)));
+        let last = intermediate_boards(&board, &sol).pop().unwrap();
+        assert_eq!(last.get_first_hand(PIECE_SILVER), 0);
+    }
+
+    #[test]
+    fn test_hand_superiority() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_GOLD, 1);
+
+        let options = SolveOptions::default();
+        let mut tt = TranspositionTable::new(1 << 20);
+        let mut minimax = Minimax::new(&options, &mut tt);
+        assert_eq!(minimax.search(&board, 3), None);
+
+        // no mate with a gold in hand means no mate without it
+        board.set_first_hand(PIECE_GOLD, 0);
+        minimax.nodes = 0;
+        assert_eq!(minimax.search(&board, 3), None);
+        assert_eq!(minimax.nodes, 1);
+
+        // a mate also holds with a silver in hand, which isn't in its proof pieces
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+        assert_eq!(minimax.search(&board, 3).map(|moves| moves.len()), Some(3));
+        board.set_first_hand(PIECE_SILVER, 1);
+        minimax.nodes = 0;
+        assert_eq!(
+            minimax.solve_first(&board, 5).map(|line| line.len()),
+            Some(3)
+        );
+        assert_eq!(minimax.nodes, 1);
+
+        // but the mate may be shorter with the silver, which is searched for exact lengths
+        minimax.exact = true;
+        minimax.nodes = 0;
+        assert!(minimax.solve_first(&board, 5).is_some());
+        assert!(minimax.nodes > 1);
+    }
+
+    #[test]
+    fn test_leaf_mate_in_3() {
+        // no mate in 3 is judged by `Board::find_mate_in_3` without visiting the children,
+        // but the rook keeps checking, so a deeper search may still mate
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_ROOK, 1);
+
+        let options = SolveOptions::default();
+        let mut tt = TranspositionTable::new(1 << 20);
+        let mut minimax = Minimax::new(&options, &mut tt);
+        assert_eq!(minimax.search(&board, 3), None);
+        assert_eq!(minimax.nodes, 1);
+        assert!(minimax.cutoff);
+    }
+
+    #[test]
+    fn test_leaf_mate_in_3_line() {
+        let mut boards = vec![];
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+        boards.push(board);
+        let mut board = Board::new();
+        board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+        boards.push(board);
+        // the defences are tied by the surplus pieces
+        let mut board = Board::new();
+        board.set_sided_piece(P(1, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 2), PIECE_BISHOP.as_first());
+        board.set_sided_piece(P(2, 3), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_SILVER, 2);
+        boards.push(board);
+
+        let options = SolveOptions::default();
+        for board in boards {
+            let board = with_all_remaining_pieces(&board);
+
+            // the line built from `Board::find_mate_in_3` without visiting the children
+            let mut tt = TranspositionTable::new(1 << 20);
+            let mut minimax = Minimax::new(&options, &mut tt);
+            minimax.root_depth = 3;
+            let line = minimax.solve_first(&board, 3).unwrap();
+            assert_eq!(minimax.nodes, 1);
+            let moves = minimax.main_line(&board, true, &line).unwrap();
+
+            // the line searched by visiting the children as the deeper nodes are
+            let mut tt = TranspositionTable::new(1 << 20);
+            let mut plain = Minimax::new(&options, &mut tt);
+            plain.root_depth = 3;
+            let mut shortest: Option<Line> = None;
+            for mv in board.enumerate_check() {
+                let mut child = board.clone();
+                child.apply_move(mv);
+                if let Some(mut line) = plain.solve_second(&child, 2) {
+                    if shortest.map_or(true, |shortest| line.len() + 1 < shortest.len()) {
+                        line.prepend(mv);
+                        line.pieces = line.pieces.before(&board, mv);
+                        shortest = Some(line);
+                    }
+                }
+            }
+            let shortest = shortest.unwrap();
+            assert_eq!(line, shortest);
+            assert_eq!(moves, plain.main_line(&board, true, &shortest).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_solve_with_options() {
+        // mate in 3, found by deepening
+        let mut board = Board::new();
+        board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+
+        let report = solve_with_options(&board, &SolveOptions::default());
+        let moves = solve(&board, 3).unwrap();
+        assert_eq!(
+            report.result,
+            SolveResult::Mate {
+                moves,
+                length: 3,
+                pieces_used: vec![]
+            }
+        );
+        assert_eq!(report.stats.depth, 3);
+        assert_eq!(report.stats.max_depth, 3);
+        assert!(report.stats.nodes > 0);
+        assert!(report.stats.tt_entries > 0 && report.stats.tt.probes > 0);
+
+        // the bishop in hand is not needed
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+        let report2 = solve_with_options(&board, &SolveOptions::default());
+        assert!(matches!(
+            report2.result,
+            SolveResult::Mate { length: 5, pieces_used, .. } if pieces_used == vec![PIECE_ROOK]
+        ));
+        assert_eq!(report2.stats.depth, 5);
+        assert_eq!(report2.stats.max_depth, 5);
+        assert!(report2.stats.nodes > report.stats.nodes);
+
+        let options = SolveOptions {
+            max_depth: Some(1),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxDepth
+            }
+        );
+        assert_eq!(report.stats.depth, 1);
+
+        let options = SolveOptions {
+            max_nodes: Some(10),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxNodes
+            }
+        );
+        // the depths 1 and 3 are searched within the nodes
+        assert_eq!(report.stats.depth, 3);
+
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let options = SolveOptions {
+            cancel: Some(cancel),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::Cancelled
+            }
+        );
+        // the first iteration isn't completed
+        assert_eq!(report.stats.depth, 0);
+
+        let options = SolveOptions {
+            deadline: Some(std::time::Instant::now()),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::Deadline
+            }
+        );
+    }
+
+    #[test]
+    fn test_solve_with_options_no_mate() {
+        // a knight only moves forward, so the checks run out
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_KNIGHT, 1);
+
+        let report = solve_with_options(&board, &SolveOptions::default());
+        assert_eq!(report.result, SolveResult::NoMate);
+        // the leaves at depth 3 don't judge whether the checks run out
+        assert_eq!(report.stats.depth, 5);
+
+        // a rook can continue checking forever, so the search stops at the maximum depth
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_ROOK, 1);
+        let options = SolveOptions {
+            max_depth: Some(5),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxDepth
+            }
+        );
+        assert_eq!(report.stats.depth, 5);
+    }
+
+    #[test]
+    fn test_progress() {
+        // a rook can continue checking forever
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_ROOK, 1);
+
+        for threads in [1, 2] {
+            let reports = Arc::new(Mutex::new(vec![]));
+            let observer = {
+                let reports = reports.clone();
+                ProgressObserver::new(Duration::ZERO, move |progress| {
+                    reports.lock().unwrap().push(progress.clone())
+                })
+            };
+            let options = SolveOptions {
+                max_depth: Some(13),
+                threads,
+                progress: Some(observer),
+                ..SolveOptions::default()
+            };
+            let report = solve_with_options(&board, &options);
+            let reports = reports.lock().unwrap();
+            assert!(!reports.is_empty());
+            for (prev, progress) in reports.iter().zip(reports.iter().skip(1)) {
+                assert!(prev.nodes <= progress.nodes && prev.depth <= progress.depth);
+                assert!(prev.elapsed <= progress.elapsed);
+            }
+            for progress in reports.iter() {
+                assert!(progress.nodes <= report.stats.nodes && progress.depth % 2 == 1);
+                assert!(progress.tt_fill > 0.0 && progress.tt_fill <= 1.0);
+                assert_eq!(progress.best_line, None);
+            }
+        }
+
+        // the observer can abort the search
+        let cancel = CancelHandle::new();
+        let observer = {
+            let cancel = cancel.clone();
+            ProgressObserver::new(Duration::ZERO, move |_| cancel.cancel())
+        };
+        let options = SolveOptions {
+            max_depth: Some(13),
+            cancel: Some(cancel),
+            progress: Some(observer),
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_with_options(&board, &options).result,
+            SolveResult::Unknown {
+                reason: StopReason::Cancelled
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_position() {
+        let solve_invalid =
+            |board: &Board| solve_with_options(board, &SolveOptions::default()).result;
+
+        let board = Board::new();
+        assert_eq!(
+            solve_invalid(&board),
+            SolveResult::InvalidPosition(InvalidPosition::NoDefenderKing)
+        );
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 4), PIECE_GOLD.as_first());
+        assert_eq!(
+            solve_invalid(&board),
+            SolveResult::InvalidPosition(InvalidPosition::DefenderInCheck)
+        );
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(4, 4), PIECE_PROMOTED_ROOK.as_first());
+        board.set_first_hand(PIECE_ROOK, 2);
+        assert_eq!(
+            solve_invalid(&board),
+            SolveResult::InvalidPosition(InvalidPosition::TooManyPieces(PIECE_ROOK))
+        );
+
+        // hands which would break the hashes
+        board.set_first_hand(PIECE_ROOK, -1);
+        assert_eq!(
+            solve_invalid(&board),
+            SolveResult::InvalidPosition(InvalidPosition::NegativeHand(PIECE_ROOK))
+        );
+        assert_eq!(solve_dfpn(&board), None);
+        assert_eq!(solve(&board, 1), None);
+        board.set_first_hand(PIECE_ROOK, 0);
+        board.set_second_hand(PIECE_KING, 1);
+        assert_eq!(
+            solve_invalid(&board),
+            SolveResult::InvalidPosition(InvalidPosition::TooManyPieces(PIECE_KING))
+        );
+        let mut table = TranspositionTable::default();
+        assert_eq!(
+            solve_dfpn_with_options(&board, &SolveOptions::default(), &mut table),
+            Err(DfpnError::InvalidPosition(InvalidPosition::TooManyPieces(
+                PIECE_KING
+            )))
+        );
+        assert_eq!(
+            solve_with_table(&board, 1, &mut TranspositionTable::default()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_defender_hand() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 1), PIECE_GOLD.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        let solve_length = |board: &Board, defender_hand| {
+            let options = SolveOptions {
+                defender_hand,
+                ..SolveOptions::default()
+            };
+            solve_with_options(board, &options)
+                .result
+                .moves()
+                .map(|m| m.len())
+        };
+
+        // the rook drop is a mate in 1 unless the second player interposes next to the king
+        assert_eq!(solve_length(&board, DefenderHand::AllRemaining), Some(3));
+        assert_eq!(solve_length(&board, DefenderHand::AsGiven), Some(1));
+        let pawn = DefenderHand::Explicit([1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(solve_length(&board, pawn), Some(3));
+        board.set_second_hand(PIECE_PAWN, 1);
+        assert_eq!(solve_length(&board, DefenderHand::AsGiven), Some(3));
+
+        // the hand is counted with the pieces on the board and in the first player's hand
+        board.set_second_hand(PIECE_ROOK, 2);
+        let options = SolveOptions {
+            defender_hand: DefenderHand::AsGiven,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_with_options(&board, &options).result,
+            SolveResult::InvalidPosition(InvalidPosition::TooManyPieces(PIECE_ROOK))
+        );
+        let options = SolveOptions {
+            defender_hand: DefenderHand::Explicit([0, 0, 0, 0, 4, 0, 0]),
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_with_options(&board, &options).result,
+            SolveResult::InvalidPosition(InvalidPosition::TooManyPieces(PIECE_GOLD))
+        );
+    }
+
+    #[test]
+    fn test_solve_with_table() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+
+        let mut tt = TranspositionTable::default();
+        let sol = solve_with_table(&board, 5, &mut tt);
+        assert_eq!(sol, solve(&board, 5));
+        let stats = tt.stats();
+        assert!(stats.stores > 0 && stats.hits > 0);
+
+        // the cached results are reused by the second search
+        let sol2 = solve_with_table(&board, 5, &mut tt);
+        assert_eq!(sol2, sol);
+        assert_eq!(tt.stats().stores, stats.stores);
+
+        // a tiny table still gives the same answer
+        let mut tt = TranspositionTable::new(0);
+        assert_eq!(solve_with_table(&board, 5, &mut tt), sol);
+        assert!(tt.stats().replacements > 0);
+    }
+}
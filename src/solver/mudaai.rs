@@ -0,0 +1,256 @@
+//! Futile interpositions (mudaai).
+//!
+//! An interposition is futile if the first player can just capture the interposed piece
+//! and mate as before. Such interpositions are not regarded as defences, so they are
+//! excluded from the length of the mate and the answer selection.
+//!
+//! The rule only names the candidates. The search still plays each of them, and a candidate
+//! is futile only if the position after the capture is mated within the length of the mate
+//! against the other defences. Otherwise it is searched as a defence like any other move.
+
+use super::hand::Hands;
+use crate::board::Board;
+use crate::common::*;
+use crate::{D, P};
+
+/// Rule to judge futile interpositions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum MudaaiRule {
+    /// Every interposition is a defence.
+    #[default]
+    Disabled,
+    /// A drop between a ranging checker and the king is a candidate if no other piece of the
+    /// second player (including the king) protects the square, so that the checker captures
+    /// it for free. It is futile if a capture of it with a check mates as before.
+    Unprotected,
+}
+
+impl MudaaiRule {
+    /// Returns whether `mv` by the second player in `board` is a candidate of a futile
+    /// interposition. Whether it is actually futile depends on the mate after the capture,
+    /// which is judged by the search.
+    pub fn may_be_futile(self, board: &Board, mv: Move) -> bool {
+        !self.captures(board, mv).is_empty()
+    }
+
+    /// Returns the captures of the interposed piece with a check if `mv` is a candidate,
+    /// and nothing otherwise.
+    fn captures(self, board: &Board, mv: Move) -> Vec<Move> {
+        let pos = match (self, mv) {
+            (MudaaiRule::Unprotected, Move::FromHand(pos, piece)) if piece.is_second() => pos,
+            _ => return vec![],
+        };
+        let checker = locate_checker(board, pos);
+        if is_protected_by_second(board, Move::NoPromotion(checker, pos)) {
+            return vec![];
+        }
+        let mut after = board.clone();
+        after.apply_move(mv);
+        let mut captures = after.enumerate_check();
+        captures.retain(|c| c.dest() == pos);
+        captures
+    }
+
+    /// Returns the moves of the second player in `board`, with the captures of the interposed
+    /// piece for each candidate of a futile interposition. The captures are empty for the
+    /// other moves.
+    pub(crate) fn split_evasions(self, board: &Board) -> (Vec<Move>, Vec<Vec<Move>>) {
+        let moves = board.enumerate_check_avoidance();
+        // the judgement doesn't depend on the dropped piece, so it's cached by cells
+        let mut cells: Vec<(P, Vec<Move>)> = vec![];
+        let captures = moves
+            .iter()
+            .map(|&mv| {
+                if self == MudaaiRule::Disabled || !matches!(mv, Move::FromHand(..)) {
+                    return vec![];
+                }
+                let pos = mv.dest();
+                match cells.iter().find(|(p, _)| *p == pos) {
+                    Some((_, captures)) => captures.clone(),
+                    None => {
+                        let captures = self.captures(board, mv);
+                        cells.push((pos, captures.clone()));
+                        captures
+                    }
+                }
+            })
+            .collect();
+        (moves, captures)
+    }
+
+    /// Returns whether the second player in `board` is mated right now: it has no moves
+    /// besides the futile interpositions after whose capture it is mated again.
+    pub(crate) fn is_mated(self, board: &Board) -> bool {
+        let (moves, captures) = self.split_evasions(board);
+        moves.iter().zip(&captures).all(|(&mv, captures)| {
+            captures
+                .iter()
+                .any(|&capture| self.is_mated(&after_capture(board, mv, capture)))
+        })
+    }
+
+    /// Returns the proof pieces of `board`, which must be mated right now.
+    pub(crate) fn mated_proof(self, board: &Board) -> Hands {
+        let (moves, captures) = self.split_evasions(board);
+        let mut children = vec![];
+        for (&mv, captures) in moves.iter().zip(&captures) {
+            let mut after = board.clone();
+            after.apply_move(mv);
+            for &capture in captures {
+                let captured = after_capture(board, mv, capture);
+                if self.is_mated(&captured) {
+                    let proof = self.mated_proof(&captured);
+                    children.push(proof.before(&after, capture).before(board, mv));
+                    break;
+                }
+            }
+        }
+        Hands::proof(board, false, &children)
+    }
+
+    /// Returns the first check among `checks` in `board` which mates under this rule.
+    pub(crate) fn find_mate_in_1(self, board: &Board, checks: &[Move]) -> Option<Move> {
+        match self {
+            MudaaiRule::Disabled => board.find_mate_in_1(checks, |b| !b.has_check_avoidance()),
+            // escapes of the king are never futile
+            MudaaiRule::Unprotected => {
+                board.find_mate_in_1(checks, |b| b.king_flights().is_empty() && self.is_mated(b))
+            }
+        }
+    }
+}
+
+/// Returns the position after the interposition `mv` on `board` and the `capture` of it.
+pub(crate) fn after_capture(board: &Board, mv: Move, capture: Move) -> Board {
+    let mut board = board.clone();
+    board.apply_move(mv);
+    board.apply_move(capture);
+    board
+}
+
+/// Returns the ranging checker behind `pos`, which must be empty and between the king and
+/// the checker.
+fn locate_checker(board: &Board, pos: P) -> P {
+    let diff = pos - board.locate_second_king();
+    let dir = D(diff.0.signum(), diff.1.signum());
+    let mut checker = pos + dir;
+    while board.get_sided_piece(checker).is_empty() {
+        checker = checker + dir;
+    }
+    checker
+}
+
+/// Returns whether the second player can recapture after the checker captures a piece
+/// interposed on the destination of `capture`.
+fn is_protected_by_second(board: &Board, capture: Move) -> bool {
+    let mut board = board.clone();
+    board.apply_move(capture);
+    board
+        .enumerate_check_avoidance()
+        .iter()
+        .any(|mv| mv.dest() == capture.dest())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::tt::TranspositionTable;
+    use crate::solver::{
+        solve_dfpn_with_options, solve_with_options, SolveOptions, SolveResult, StopReason,
+    };
+
+    #[test]
+    fn test_mudaai() {
+        // ▲７三角打: an interposition at ８二 is futile since the king can't recapture there
+        // because of the rook on ７二, and ▲同飛成 mates
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 2), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(1, 2), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(0, 5), PIECE_KNIGHT.as_second());
+        board.set_first_hand(PIECE_BISHOP, 1);
+
+        let check = Move::FromHand(P(2, 2), PIECE_BISHOP.as_first());
+        let mut after = board.clone();
+        after.set_second_hand(PIECE_PAWN, 1);
+        after.apply_move(check);
+        let interposition = Move::FromHand(P(1, 1), PIECE_PAWN.as_second());
+        assert!(MudaaiRule::Unprotected.may_be_futile(&after, interposition));
+        assert!(!MudaaiRule::Disabled.may_be_futile(&after, interposition));
+        assert!(MudaaiRule::Unprotected.is_mated(&after));
+        assert!(!MudaaiRule::Disabled.is_mated(&after));
+
+        let options = SolveOptions {
+            max_depth: Some(3),
+            mudaai: MudaaiRule::Unprotected,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_with_options(&board, &options).result,
+            SolveResult::Mate {
+                moves: vec![check],
+                length: 1,
+                pieces_used: vec![PIECE_BISHOP],
+            }
+        );
+        let mut table = TranspositionTable::default();
+        assert_eq!(
+            solve_dfpn_with_options(&board, &options, &mut table),
+            Ok(Some(vec![check]))
+        );
+
+        let options = SolveOptions {
+            mudaai: MudaaiRule::Disabled,
+            ..options
+        };
+        let result = solve_with_options(&board, &options).result;
+        assert_eq!(result.moves().map(|m| m.len()), Some(3));
+    }
+
+    #[test]
+    fn test_escape_after_capture() {
+        // ▲７七角打: an interposition at ８六 can't be recaptured, but the king escapes to ９六
+        // after both ▲同角 and ▲同銀, so it is a defence and there is no mate
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(3, 1), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(4, 2), PIECE_SILVER.as_first());
+        board.set_sided_piece(P(5, 2), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_BISHOP, 1);
+
+        let mut after = board.clone();
+        after.set_second_hand(PIECE_PAWN, 1);
+        after.apply_move(Move::FromHand(P(6, 2), PIECE_BISHOP.as_first()));
+        let interposition = Move::FromHand(P(5, 1), PIECE_PAWN.as_second());
+        assert!(MudaaiRule::Unprotected.may_be_futile(&after, interposition));
+        assert!(!MudaaiRule::Unprotected.is_mated(&after));
+
+        let options = SolveOptions {
+            max_depth: Some(5),
+            mudaai: MudaaiRule::Unprotected,
+            ..SolveOptions::default()
+        };
+        assert_eq!(
+            solve_with_options(&board, &options).result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxDepth
+            }
+        );
+    }
+
+    #[test]
+    fn test_protected_interposition() {
+        // an interposition next to the king can be recaptured by the king
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 8), PIECE_PROMOTED_ROOK.as_first());
+        board.set_second_hand(PIECE_PAWN, 1);
+
+        let near = Move::FromHand(P(0, 1), PIECE_PAWN.as_second());
+        let far = Move::FromHand(P(0, 4), PIECE_PAWN.as_second());
+        assert!(!MudaaiRule::Unprotected.may_be_futile(&board, near));
+        assert!(MudaaiRule::Unprotected.may_be_futile(&board, far));
+    }
+}
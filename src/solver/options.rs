@@ -0,0 +1,183 @@
+//! Options and limits of the search.
+
+use super::mudaai::MudaaiRule;
+use super::ordering::MoveOrdering;
+use super::tt::DEFAULT_TT_MEMORY;
+use crate::common::*;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Handle to cancel a running search from another thread.
+#[derive(Clone, Debug, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> CancelHandle {
+        CancelHandle::default()
+    }
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Progress of a running search, passed to a `ProgressObserver`.
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// Number of nodes searched so far.
+    pub nodes: u64,
+    /// Depth of the current iteration of iterative deepening.
+    pub depth: i32,
+    /// The shortest mate found so far in the current iteration.
+    pub best_line: Option<Vec<Move>>,
+    pub elapsed: Duration,
+    /// Fraction of the transposition table in use.
+    pub tt_fill: f64,
+}
+
+/// Callback called periodically during a search, e.g. to show the progress.
+/// The search can be aborted from it with a `CancelHandle`.
+#[derive(Clone)]
+pub struct ProgressObserver {
+    callback: Arc<dyn Fn(&Progress) + Send + Sync>,
+    /// Minimum time between two calls.
+    pub interval: Duration,
+}
+
+impl ProgressObserver {
+    pub fn new(
+        interval: Duration,
+        callback: impl Fn(&Progress) + Send + Sync + 'static,
+    ) -> ProgressObserver {
+        ProgressObserver {
+            callback: Arc::new(callback),
+            interval,
+        }
+    }
+
+    pub(crate) fn notify(&self, progress: &Progress) {
+        (self.callback)(progress)
+    }
+}
+
+impl fmt::Debug for ProgressObserver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ProgressObserver")
+            .field("interval", &self.interval)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Where and how often to save checkpoints of a search, which can be resumed with `resume`.
+///
+/// A checkpoint is also saved when the search stops before finding the answer. Failures to
+/// save are ignored so that the search goes on; the file is replaced atomically, so the
+/// previous checkpoint is kept then.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckpointOptions {
+    /// The path is saved in the checkpoint too, so it must be valid UTF-8, or every save fails.
+    pub path: PathBuf,
+    /// Minimum time between two periodic checkpoints.
+    pub interval: Duration,
+}
+
+/// Pieces in the second player's hand during the search.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "type", content = "counts", rename_all = "snake_case")
+)]
+pub enum DefenderHand {
+    /// Every piece which is neither on the board nor in the first player's hand,
+    /// as in the classic rules.
+    #[default]
+    AllRemaining,
+    /// The second player's hand on the board, e.g. for positions from real games.
+    AsGiven,
+    /// The numbers of pawns, lances, knights, silvers, golds, bishops and rooks,
+    /// indexed by `Piece`.
+    Explicit([u8; PIECE_TYPES - 1]),
+}
+
+#[derive(Clone, Debug)]
+pub struct SolveOptions {
+    /// Maximum length of the mate to search, or `None` to deepen until the search finishes.
+    pub max_depth: Option<i32>,
+    /// Maximum number of nodes to search.
+    pub max_nodes: Option<u64>,
+    pub deadline: Option<Instant>,
+    pub cancel: Option<CancelHandle>,
+    /// Memory size of the transposition table in bytes.
+    pub tt_memory: usize,
+    pub mudaai: MudaaiRule,
+    pub defender_hand: DefenderHand,
+    /// Heuristics to order the moves. The result doesn't depend on them.
+    pub ordering: MoveOrdering,
+    /// Number of threads to search with. The result doesn't depend on it.
+    pub threads: usize,
+    pub progress: Option<ProgressObserver>,
+    pub checkpoint: Option<CheckpointOptions>,
+}
+
+impl Default for SolveOptions {
+    fn default() -> SolveOptions {
+        SolveOptions {
+            max_depth: None,
+            max_nodes: None,
+            deadline: None,
+            cancel: None,
+            tt_memory: DEFAULT_TT_MEMORY,
+            mudaai: MudaaiRule::default(),
+            defender_hand: DefenderHand::default(),
+            ordering: MoveOrdering::default(),
+            threads: 1,
+            progress: None,
+            checkpoint: None,
+        }
+    }
+}
+
+/// Reason why a search stopped before finding the answer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "snake_case"))]
+pub enum StopReason {
+    MaxDepth,
+    MaxNodes,
+    Deadline,
+    Cancelled,
+}
+
+/// Number of nodes between checks of the deadline and the cancellation.
+pub(crate) const CHECK_INTERVAL: u64 = 1024;
+
+impl SolveOptions {
+    /// Returns the reason to stop the search when visiting the `nodes`-th node (1-indexed), if any.
+    pub(crate) fn check_stop(&self, nodes: u64) -> Option<StopReason> {
+        if let Some(max_nodes) = self.max_nodes {
+            if nodes > max_nodes {
+                return Some(StopReason::MaxNodes);
+            }
+        }
+        if (nodes - 1) % CHECK_INTERVAL != 0 {
+            return None;
+        }
+        if let Some(cancel) = &self.cancel {
+            if cancel.is_cancelled() {
+                return Some(StopReason::Cancelled);
+            }
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Some(StopReason::Deadline);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,291 @@
+//! Move ordering heuristics.
+//!
+//! The heuristics only change the order in which the moves are searched, and so the number of
+//! nodes. Ties among the mates (or the defences) of the same length are still broken by the
+//! order of the move generator, so the main line doesn't depend on the ordering.
+
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+
+/// Order of the king moves and the other evasions (interpositions and captures of the checker).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EvasionOrder {
+    /// The order of the move generator.
+    #[default]
+    Generated,
+    KingMovesFirst,
+    InterpositionsFirst,
+}
+
+/// Heuristics to order the moves. All of them are disabled by default.
+///
+/// The moves are sorted by the killer moves first, then by the other heuristics, then by the
+/// history, and finally by the order of the move generator.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MoveOrdering {
+    /// Checks capturing a piece next to the king come first.
+    pub captures_near_king: bool,
+    /// Drops onto the cells where the king could escape come first.
+    pub drops_on_flights: bool,
+    /// The last two moves which mated or refuted at the same ply come first.
+    pub killers: bool,
+    /// Moves of a piece to a cell are preferred by how often such moves mated or refuted,
+    /// weighted by the depth of the subtree.
+    pub history: bool,
+    pub evasions: EvasionOrder,
+}
+
+impl MoveOrdering {
+    /// All the heuristics, with interpositions first.
+    pub fn all() -> MoveOrdering {
+        MoveOrdering {
+            captures_near_king: true,
+            drops_on_flights: true,
+            killers: true,
+            history: true,
+            evasions: EvasionOrder::InterpositionsFirst,
+        }
+    }
+}
+
+/// How often the first move searched turned out to be the best one.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct OrderingStats {
+    /// Number of attacker nodes with a mate.
+    pub mates: u64,
+    /// Number of them where the first check searched is the shortest mate.
+    pub first_mates: u64,
+    /// Number of defender nodes where an evasion escapes the mate, so the others are skipped.
+    pub refutations: u64,
+    /// Number of them where the first evasion searched escapes.
+    pub first_refutations: u64,
+}
+
+impl OrderingStats {
+    pub(crate) fn merge(&mut self, other: &OrderingStats) {
+        self.mates += other.mates;
+        self.first_mates += other.first_mates;
+        self.refutations += other.refutations;
+        self.first_refutations += other.first_refutations;
+    }
+}
+
+const CELLS: usize = (BOARD_SIZE * BOARD_SIZE) as usize;
+const HISTORY_SIZE: usize = 2 * PIECE_TYPES * 2 * CELLS;
+
+/// Killer moves and history collected during the search.
+pub(crate) struct OrderingTables {
+    killers: Vec<[Option<Move>; 2]>,
+    history: Vec<u32>,
+}
+
+impl Default for OrderingTables {
+    fn default() -> OrderingTables {
+        OrderingTables {
+            killers: vec![],
+            history: vec![0; HISTORY_SIZE],
+        }
+    }
+}
+
+/// Index in the history of `mv` on `board`, by the player, the moving piece and the destination.
+fn history_index(board: &Board, mv: Move, or_node: bool) -> usize {
+    let piece = match mv {
+        Move::FromHand(_, piece) => piece,
+        Move::NoPromotion(src, _) | Move::Promotion(src, _) => board.get_sided_piece(src),
+    };
+    let dest = mv.dest();
+    let cell = (dest.y() * BOARD_SIZE + dest.x()) as usize;
+    ((or_node as usize * 2 * PIECE_TYPES) + piece.to_piece().0 as usize) * CELLS + cell
+}
+
+fn is_next_to(a: P, b: P) -> bool {
+    (a.y() - b.y()).abs() <= 1 && (a.x() - b.x()).abs() <= 1
+}
+
+impl OrderingTables {
+    /// Returns the indices of `moves` in the order to search them at `ply`.
+    pub(crate) fn order(
+        &self,
+        ordering: &MoveOrdering,
+        board: &Board,
+        moves: &[Move],
+        or_node: bool,
+        ply: usize,
+    ) -> Vec<usize> {
+        let mut order = (0..moves.len()).collect::<Vec<_>>();
+        if *ordering == MoveOrdering::default() {
+            return order;
+        }
+
+        let king = board.find_second_king();
+        let flights = if or_node && ordering.drops_on_flights {
+            board.king_flights()
+        } else {
+            vec![]
+        };
+        let killers = match self.killers.get(ply) {
+            Some(killers) if ordering.killers => *killers,
+            _ => [None, None],
+        };
+        let key = |&i: &usize| {
+            let mv = moves[i];
+            let killer = killers
+                .iter()
+                .position(|&k| k == Some(mv))
+                .map_or(0, |n| 2 - n);
+            let preferred = if or_node {
+                let captures = ordering.captures_near_king
+                    && board.get_sided_piece(mv.dest()).is_second()
+                    && king.is_some_and(|king| is_next_to(king, mv.dest()));
+                let drops = matches!(mv, Move::FromHand(..)) && flights.contains(&mv.dest());
+                captures || drops
+            } else {
+                let king_move = mv.src().is_some() && mv.src() == king;
+                match ordering.evasions {
+                    EvasionOrder::Generated => false,
+                    EvasionOrder::KingMovesFirst => king_move,
+                    EvasionOrder::InterpositionsFirst => !king_move,
+                }
+            };
+            let history = if ordering.history {
+                self.history[history_index(board, mv, or_node)]
+            } else {
+                0
+            };
+            std::cmp::Reverse((killer, preferred, history))
+        };
+        // the sort is stable, so ties are kept in the order of the move generator
+        order.sort_by_key(key);
+        order
+    }
+
+    /// Records that `mv` mated (if `or_node`) or refuted the mate at `ply`, where `max_depth`
+    /// plies were left.
+    pub(crate) fn record(
+        &mut self,
+        ordering: &MoveOrdering,
+        board: &Board,
+        mv: Move,
+        or_node: bool,
+        ply: usize,
+        max_depth: i32,
+    ) {
+        if ordering.killers {
+            if self.killers.len() <= ply {
+                self.killers.resize(ply + 1, [None, None]);
+            }
+            let killers = &mut self.killers[ply];
+            if killers[0] != Some(mv) {
+                killers[1] = killers[0];
+                killers[0] = Some(mv);
+            }
+        }
+        if ordering.history {
+            let weight = (max_depth * max_depth) as u32;
+            let entry = &mut self.history[history_index(board, mv, or_node)];
+            *entry = entry.saturating_add(weight);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::solver::{solve_with_options, SolveOptions, SolveResult};
+
+    #[test]
+    fn test_order() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 4), PIECE_PAWN.as_second());
+        board.set_sided_piece(P(4, 4), PIECE_LANCE.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+        let moves = board.enumerate_check();
+        let is_capture = |mv: Move| mv.dest() == P(1, 4);
+        let other = moves.iter().position(|&mv| !is_capture(mv)).unwrap();
+
+        let mut tables = OrderingTables::default();
+        let order = tables.order(&MoveOrdering::default(), &board, &moves, true, 0);
+        assert_eq!(order, (0..moves.len()).collect::<Vec<_>>());
+
+        let ordering = MoveOrdering {
+            captures_near_king: true,
+            killers: true,
+            ..MoveOrdering::default()
+        };
+        let order = tables.order(&ordering, &board, &moves, true, 0);
+        assert!(is_capture(moves[order[0]]));
+
+        // a killer move comes before the capture
+        tables.record(&ordering, &board, moves[other], true, 0, 3);
+        let order = tables.order(&ordering, &board, &moves, true, 0);
+        assert_eq!(order[0], other);
+        assert!(is_capture(moves[order[1]]));
+    }
+
+    #[test]
+    fn test_ordering_node_counts() {
+        let mut problems = vec![];
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+        problems.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(1, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 2), PIECE_BISHOP.as_first());
+        board.set_sided_piece(P(2, 3), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_SILVER, 2);
+        problems.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+        problems.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 2), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 0), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+        problems.push(board);
+
+        let orderings = [
+            MoveOrdering::default(),
+            MoveOrdering {
+                evasions: EvasionOrder::KingMovesFirst,
+                ..MoveOrdering::default()
+            },
+            MoveOrdering::all(),
+        ];
+        let mut first_mates = vec![0; orderings.len()];
+        for board in &problems {
+            let expected = solve_with_options(board, &SolveOptions::default());
+            for (i, &ordering) in orderings.iter().enumerate() {
+                let options = SolveOptions {
+                    ordering,
+                    ..SolveOptions::default()
+                };
+                let report = solve_with_options(board, &options);
+                // only the number of nodes depends on the ordering
+                assert_eq!(report.result, expected.result);
+                assert!(report.stats.nodes > 0);
+                let stats = report.stats.ordering;
+                // the mates in 3 are found at the leaves without ordering the moves
+                let leaf_mate =
+                    matches!(expected.result, SolveResult::Mate { length, .. } if length <= 3);
+                assert!(stats.first_mates <= stats.mates && (stats.mates > 0 || leaf_mate));
+                assert!(stats.first_refutations <= stats.refutations);
+                first_mates[i] += stats.first_mates;
+            }
+        }
+        // the killer moves and the history find the mates earlier
+        assert!(first_mates[2] > first_mates[0]);
+    }
+}
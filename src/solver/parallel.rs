@@ -0,0 +1,188 @@
+//! Parallel search by splitting the root moves among threads.
+//!
+//! Each thread takes the next unsearched check at the root and searches it with its own
+//! `Minimax`, sharing a transposition table and the node count with the other threads.
+//! The results are combined in the order of the moves, so the main line doesn't depend on
+//! the scheduling of the threads.
+
+use super::tt::ShardedTranspositionTable;
+use super::{
+    CheckpointState, Iteration, Line, Minimax, MinimaxEntry, ProgressState, SharedState,
+    SolveOptions,
+};
+use crate::board::Board;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+pub(super) fn search(
+    board: &Board,
+    depth: i32,
+    options: &SolveOptions,
+    tt: &ShardedTranspositionTable<MinimaxEntry>,
+    shared: &SharedState,
+    progress: &ProgressState,
+    checkpoint: &CheckpointState,
+) -> Iteration {
+    // the root itself
+    shared.nodes.fetch_add(1, Ordering::Relaxed);
+    *progress.best_line.lock().unwrap() = None;
+
+    let moves = board.enumerate_check();
+    let next = AtomicUsize::new(0);
+    let results: Vec<Mutex<Option<Line>>> = moves.iter().map(|_| Mutex::new(None)).collect();
+
+    let mut iteration = Iteration {
+        line: None,
+        cutoff: false,
+        stopped: None,
+        max_ply: 0,
+    };
+    thread::scope(|s| {
+        let workers = (0..options.threads.min(moves.len()))
+            .map(|_| {
+                s.spawn(|| {
+                    let mut tt = tt;
+                    let mut minimax = Minimax::new(options, &mut tt);
+                    minimax.shared = Some(shared);
+                    minimax.progress = Some(progress);
+                    minimax.checkpoint = Some(checkpoint);
+                    minimax.root_depth = depth;
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= moves.len() {
+                            break;
+                        }
+                        let mut child = board.clone();
+                        child.apply_move(moves[i]);
+                        let res = minimax.solve_second(&child, depth - 1);
+                        if minimax.stopped.is_some() {
+                            break;
+                        }
+                        if let Some(line) = &res {
+                            minimax.offer_progress(board, moves[i], line);
+                        }
+                        *results[i].lock().unwrap() = res;
+                    }
+                    shared
+                        .ordering
+                        .lock()
+                        .unwrap()
+                        .merge(&minimax.ordering_stats);
+                    (minimax.cutoff, minimax.stopped, minimax.max_ply)
+                })
+            })
+            .collect::<Vec<_>>();
+        for worker in workers {
+            let (cutoff, stopped, max_ply) = worker.join().unwrap();
+            iteration.cutoff |= cutoff;
+            iteration.stopped = iteration.stopped.or(stopped);
+            iteration.max_ply = iteration.max_ply.max(max_ply);
+        }
+    });
+    if iteration.stopped.is_some() {
+        return iteration;
+    }
+
+    // the same selection as `Minimax::solve_first`
+    let mut shortest: Option<Line> = None;
+    for (&mv, result) in moves.iter().zip(results) {
+        if let Some(mut line) = result.into_inner().unwrap() {
+            if shortest
+                .as_ref()
+                .map_or(true, |line2| line.len() + 1 < line2.len())
+            {
+                line.prepend(mv);
+                line.pieces = line.pieces.before(board, mv);
+                shortest = Some(line);
+            }
+        }
+    }
+    if let Some(line) = shortest {
+        // the main line is followed in the shared table
+        let mut tt = tt;
+        let mut minimax = Minimax::new(options, &mut tt);
+        minimax.shared = Some(shared);
+        minimax.root_depth = depth;
+        match minimax.main_line(board, true, &line) {
+            Some(moves) => iteration.line = Some((moves, line.pieces)),
+            None => iteration.stopped = minimax.stopped,
+        }
+    }
+    iteration
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{solve_with_options, SolveResult, StopReason};
+    use super::*;
+    use crate::common::*;
+    use crate::P;
+
+    fn problems() -> Vec<Board> {
+        let mut ret = vec![];
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+        ret.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(1, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 2), PIECE_BISHOP.as_first());
+        board.set_sided_piece(P(2, 3), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_SILVER, 2);
+        ret.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+        ret.push(board);
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_KNIGHT, 1);
+        ret.push(board);
+
+        ret
+    }
+
+    #[test]
+    fn test_parallel_search() {
+        for board in problems() {
+            let expected = solve_with_options(&board, &SolveOptions::default()).result;
+            for threads in [2, 4, 8] {
+                let options = SolveOptions {
+                    threads,
+                    ..SolveOptions::default()
+                };
+                for _ in 0..3 {
+                    let report = solve_with_options(&board, &options);
+                    assert_eq!(report.result, expected);
+                    assert!(report.stats.nodes > 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_parallel_search_limits() {
+        let board = problems().swap_remove(2);
+        let options = SolveOptions {
+            threads: 4,
+            max_nodes: Some(100),
+            ..SolveOptions::default()
+        };
+        let report = solve_with_options(&board, &options);
+        assert_eq!(
+            report.result,
+            SolveResult::Unknown {
+                reason: StopReason::MaxNodes
+            }
+        );
+        assert!(report.stats.nodes <= 100 + options.threads as u64);
+    }
+}
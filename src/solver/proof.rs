@@ -0,0 +1,273 @@
+//! Proof trees, which contain an attacker reply to every defence instead of the main line only.
+
+use super::tt::TranspositionTable;
+use super::{
+    solve_with_options, with_defender_hand, DefenderHand, Minimax, SolveOptions, SolveResult,
+    StopReason,
+};
+use crate::board::Board;
+use crate::common::*;
+use crate::notation::format_move;
+use std::fmt;
+
+/// A move in a proof tree with the replies to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofNode {
+    pub mv: Move,
+    /// Number of plies until the mate, including `mv`.
+    pub length: usize,
+    /// Number of moves in the subtree, including `mv`.
+    pub nodes: usize,
+    /// Every defence if `mv` is an attacker move, or the attacker reply if it is a defence.
+    pub children: Vec<ProofNode>,
+}
+
+/// Proof of a mate, which can be exported as JSON with the `serde` feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ProofTree {
+    /// The first move of the attacker.
+    pub root: ProofNode,
+    /// The second player's hand the tree is built with.
+    pub defender_hand: DefenderHand,
+}
+
+/// Reason why `solve_proof_tree` fails.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProofError {
+    /// The result of the search if no mate is found, or the reason why the search or the
+    /// building of the tree stopped.
+    Search(SolveResult),
+    /// The mate isn't found again against some defence, e.g. because the search depended on
+    /// entries replaced in the table.
+    Inconsistent,
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::Search(result) => write!(f, "{}", result),
+            ProofError::Inconsistent => write!(f, "the mate is not found again against a defence"),
+        }
+    }
+}
+
+impl ProofNode {
+    fn new(mv: Move, children: Vec<ProofNode>) -> ProofNode {
+        ProofNode {
+            mv,
+            length: 1 + children.iter().map(|c| c.length).max().unwrap_or(0),
+            nodes: 1 + children.iter().map(|c| c.nodes).sum::<usize>(),
+            children,
+        }
+    }
+}
+
+impl ProofTree {
+    /// Formats the tree as an indented outline of moves in the Japanese notation, starting from `board`.
+    pub fn outline(&self, board: &Board) -> String {
+        let mut ret = format!("mate in {}, {} nodes\n", self.root.length, self.root.nodes);
+        let board = with_defender_hand(board, self.defender_hand);
+        write_outline(&mut ret, &board, &self.root, None, 0);
+        ret
+    }
+}
+
+fn write_outline(
+    out: &mut String,
+    board: &Board,
+    node: &ProofNode,
+    prev: Option<Move>,
+    ply: usize,
+) {
+    for _ in 0..ply {
+        out.push_str("  ");
+    }
+    out.push(if ply % 2 == 0 { '▲' } else { '△' });
+    out.push_str(&format_move(board, node.mv, prev));
+    out.push_str(&format!(" ({})\n", node.length));
+
+    let mut board = board.clone();
+    board.apply_move(node.mv);
+    for child in &node.children {
+        write_outline(out, &board, child, Some(node.mv), ply + 1);
+    }
+}
+
+/// Solves the problem and builds the proof tree along the main line.
+/// Fails with the result of the search if no mate is found or the building stops,
+/// and with `ProofError::Inconsistent` if the mate isn't found again against some defence.
+pub fn solve_proof_tree(board: &Board, options: &SolveOptions) -> Result<ProofTree, ProofError> {
+    let moves = match solve_with_options(board, options).result {
+        SolveResult::Mate { moves, .. } => moves,
+        result => return Err(ProofError::Search(result)),
+    };
+
+    let board = with_defender_hand(board, options.defender_hand);
+    let mut tt = TranspositionTable::new(options.tt_memory);
+    let mut minimax = Minimax::new(options, &mut tt);
+    minimax.root_depth = moves.len() as i32;
+    let root = build(
+        &mut minimax,
+        &board,
+        moves[0],
+        &moves[1..],
+        moves.len() as i32,
+    )?;
+    Ok(ProofTree {
+        root,
+        defender_hand: options.defender_hand,
+    })
+}
+
+/// Builds the subtree of the attacker move `mv` mating within `max_depth`.
+/// The replies are taken from `main_line` as long as the defences follow it.
+fn build(
+    minimax: &mut Minimax,
+    board: &Board,
+    mv: Move,
+    main_line: &[Move],
+    max_depth: i32,
+) -> Result<ProofNode, ProofError> {
+    let mut board = board.clone();
+    board.apply_move(mv);
+
+    let (defences, captures) = minimax.options.mudaai.split_evasions(&board);
+    // the candidates of futile interpositions are judged after the other defences are built
+    let order = (0..defences.len())
+        .filter(|&i| captures[i].is_empty())
+        .chain((0..defences.len()).filter(|&i| !captures[i].is_empty()));
+    let mut children: Vec<(usize, ProofNode)> = vec![];
+    let mut other_length = None;
+    for i in order {
+        let defence = defences[i];
+        if !captures[i].is_empty() {
+            let length = *other_length.get_or_insert_with(|| {
+                children.iter().map(|(_, c)| c.length).max().unwrap_or(0) as i32
+            });
+            let futile = minimax.futile_proof(&board, defence, &captures[i], length);
+            if let Some(reason) = minimax.stopped {
+                return Err(stopped(reason));
+            }
+            if futile.is_some() {
+                continue;
+            }
+        }
+        let mut child = board.clone();
+        child.apply_move(defence);
+
+        let (reply, rest) = if main_line.first() == Some(&defence) {
+            (main_line[1], &main_line[2..])
+        } else {
+            let line = minimax.solve_first(&child, max_depth - 2);
+            if let Some(reason) = minimax.stopped {
+                return Err(stopped(reason));
+            }
+            match line.and_then(|line| line.first) {
+                Some(reply) => (reply, &[][..]),
+                None => return Err(ProofError::Inconsistent),
+            }
+        };
+        let reply = build(minimax, &child, reply, rest, max_depth - 2)?;
+        children.push((i, ProofNode::new(defence, vec![reply])));
+    }
+    children.sort_by_key(|&(i, _)| i);
+    let children = children.into_iter().map(|(_, c)| c).collect();
+    Ok(ProofNode::new(mv, children))
+}
+
+fn stopped(reason: StopReason) -> ProofError {
+    ProofError::Search(SolveResult::Unknown { reason })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::MudaaiRule;
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_proof_tree() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+
+        // the interpositions after ▲３二飛成 are futile
+        let options = SolveOptions {
+            mudaai: MudaaiRule::Unprotected,
+            ..SolveOptions::default()
+        };
+        let tree = solve_proof_tree(&board, &options).unwrap();
+        assert_eq!(tree.root.length, 5);
+        assert_eq!(tree.root.nodes, 7);
+        assert_eq!(
+            tree.root
+                .children
+                .iter()
+                .map(|c| c.length)
+                .collect::<Vec<_>>(),
+            vec![4, 2]
+        );
+        assert_eq!(
+            tree.outline(&board),
+            "mate in 5, 7 nodes
+▲２二飛打 (5)
+  △３一玉 (4)
+    ▲４二飛成 (3)
+      △２一玉 (2)
+        ▲２二龍 (1)
+  △１一玉 (2)
+    ▲３二飛成 (1)
+"
+        );
+
+        // without the rule, each of them is answered
+        let tree = solve_proof_tree(&board, &SolveOptions::default()).unwrap();
+        assert_eq!(tree.root.length, 5);
+        assert_eq!(tree.root.nodes, 19);
+
+        // nor are there any if the second player has no pieces in hand
+        let options = SolveOptions {
+            defender_hand: DefenderHand::AsGiven,
+            ..SolveOptions::default()
+        };
+        let tree = solve_proof_tree(&board, &options).unwrap();
+        assert_eq!(tree.root.nodes, 7);
+        assert_eq!(tree.defender_hand, DefenderHand::AsGiven);
+        assert!(tree
+            .outline(&board)
+            .starts_with("mate in 5, 7 nodes\n▲２二飛打 (5)\n"));
+
+        let mut board = Board::new();
+        board.set_sided_piece(P(4, 4), PIECE_KING.as_second());
+        board.set_first_hand(PIECE_KNIGHT, 1);
+        assert_eq!(
+            solve_proof_tree(&board, &SolveOptions::default()),
+            Err(ProofError::Search(SolveResult::NoMate))
+        );
+    }
+
+    #[test]
+    fn test_proof_tree_inconsistent() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 6), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_ROOK, 1);
+        board.set_first_hand(PIECE_BISHOP, 1);
+        let board = with_defender_hand(&board, DefenderHand::AllRemaining);
+
+        // ▲２二飛打 mates in 5, which isn't found again within 3
+        let options = SolveOptions::default();
+        let mut tt = TranspositionTable::new(options.tt_memory);
+        let mut minimax = Minimax::new(&options, &mut tt);
+        minimax.root_depth = 3;
+        let mv = Move::FromHand(P(1, 7), PIECE_ROOK.as_first());
+        assert_eq!(
+            build(&mut minimax, &board, mv, &[], 3),
+            Err(ProofError::Inconsistent)
+        );
+    }
+}
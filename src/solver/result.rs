@@ -0,0 +1,106 @@
+//! Results and statistics of the search.
+
+use super::options::StopReason;
+use super::ordering::OrderingStats;
+use super::tt::TTStats;
+use crate::common::*;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(tag = "type", rename_all = "snake_case"))]
+pub enum SolveResult {
+    /// The main line of the shortest mate.
+    Mate {
+        moves: Vec<Move>,
+        length: usize,
+        /// Pieces in the first player's hand needed to mate against every defence.
+        pieces_used: Vec<Piece>,
+    },
+    /// It is proven that there is no mate.
+    NoMate,
+    /// The search stopped before finding the answer.
+    Unknown {
+        reason: StopReason,
+    },
+    InvalidPosition(InvalidPosition),
+}
+
+/// Reason why a position can't be solved.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "reason", content = "piece", rename_all = "snake_case")
+)]
+pub enum InvalidPosition {
+    /// The second player has no king.
+    NoDefenderKing,
+    /// The second player's king is already in check, so it could be captured.
+    DefenderInCheck,
+    /// There are more pieces of the kind than in a set.
+    TooManyPieces(Piece),
+    /// A hand has a negative number of pieces of the kind.
+    NegativeHand(Piece),
+}
+
+impl SolveResult {
+    /// Returns the main line if a mate is found.
+    pub fn moves(&self) -> Option<&[Move]> {
+        match self {
+            SolveResult::Mate { moves, .. } => Some(moves),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SolveResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveResult::Mate { length, .. } => write!(f, "mate in {}", length),
+            SolveResult::NoMate => write!(f, "no mate"),
+            SolveResult::Unknown { reason } => write!(f, "unknown ({:?})", reason),
+            SolveResult::InvalidPosition(reason) => write!(f, "invalid position ({})", reason),
+        }
+    }
+}
+
+impl fmt::Display for InvalidPosition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InvalidPosition::NoDefenderKing => write!(f, "no king of the second player"),
+            InvalidPosition::DefenderInCheck => {
+                write!(f, "the king of the second player is in check")
+            }
+            InvalidPosition::TooManyPieces(piece) => write!(f, "too many {}", piece.get_name()),
+            InvalidPosition::NegativeHand(piece) => {
+                write!(f, "negative number of {} in hand", piece.get_name())
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveStats {
+    /// Number of nodes searched.
+    pub nodes: u64,
+    /// The largest depth searched completely by iterative deepening.
+    pub depth: i32,
+    /// The largest number of plies from the root among the nodes searched.
+    pub max_depth: i32,
+    pub tt: TTStats,
+    /// Number of entries in the transposition table at the end.
+    pub tt_entries: usize,
+    pub tt_capacity: usize,
+    pub ordering: OrderingStats,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SolveReport {
+    pub result: SolveResult,
+    pub stats: SolveStats,
+}
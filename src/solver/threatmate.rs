@@ -0,0 +1,154 @@
+//! Detection of threatmates (tsumero) for game analysis.
+//!
+//! A position with the second player to move threatens mate if the first player would have
+//! a mate should the second player pass. The first player's king, if any, is ignored as in
+//! the problems.
+
+use super::{
+    solve_with_options, with_defender_hand, DefenderHand, SolveOptions, SolveReport, SolveResult,
+    StopReason,
+};
+use crate::board::Board;
+use crate::common::*;
+
+/// A move of the second player and the threat after it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreatmateReply {
+    pub mv: Move,
+    /// Result of solving the position after `mv`.
+    pub result: SolveResult,
+}
+
+impl ThreatmateReply {
+    /// Whether the threatmate remains after the move.
+    pub fn keeps_threat(&self) -> bool {
+        matches!(self.result, SolveResult::Mate { .. })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ThreatmateReport {
+    /// The threatmate in the position. The replies are searched unless it is invalid.
+    pub result: SolveResult,
+    /// Every move of the second player, in the order of `Board::enumerate_second_moves`.
+    pub replies: Vec<ThreatmateReply>,
+    /// Why the search of the replies stopped, if it didn't finish.
+    pub stopped: Option<StopReason>,
+}
+
+/// Searches the mate which `board` threatens, where the second player is to move and not in
+/// check. The main line of the result is the threatened mate.
+pub fn solve_threatmate(board: &Board, options: &SolveOptions) -> SolveReport {
+    solve_with_options(board, options)
+}
+
+/// Searches the threatmate, and whether it remains after each move of the second player.
+/// The limits of `options` apply to each search, and the deadline or the cancellation stop
+/// the whole analysis.
+pub fn analyze_threatmate(board: &Board, options: &SolveOptions) -> ThreatmateReport {
+    let result = solve_threatmate(board, options).result;
+    let mut report = ThreatmateReport {
+        result,
+        replies: vec![],
+        stopped: None,
+    };
+    match report.result {
+        SolveResult::InvalidPosition(_) => return report,
+        SolveResult::Unknown { reason } if stops_analysis(reason) => {
+            report.stopped = Some(reason);
+            return report;
+        }
+        _ => (),
+    }
+
+    // the hand after each move is the one left by the move
+    let board = with_defender_hand(board, options.defender_hand);
+    let options = SolveOptions {
+        defender_hand: DefenderHand::AsGiven,
+        checkpoint: None,
+        ..options.clone()
+    };
+    for mv in board.enumerate_second_moves() {
+        let mut child = board.clone();
+        child.apply_move(mv);
+        let result = solve_threatmate(&child, &options).result;
+        if let SolveResult::Unknown { reason } = result {
+            if stops_analysis(reason) {
+                report.stopped = Some(reason);
+                return report;
+            }
+        }
+        report.replies.push(ThreatmateReply { mv, result });
+    }
+    report
+}
+
+/// Whether a search stopped by `reason` would stop the following searches too.
+fn stops_analysis(reason: StopReason) -> bool {
+    matches!(reason, StopReason::Deadline | StopReason::Cancelled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::CancelHandle;
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_threatmate() {
+        // ▲９二飛打 threatens to mate, unless the king runs away
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 1), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(0, 3), PIECE_GOLD.as_second());
+        board.set_first_hand(PIECE_ROOK, 1);
+        let options = SolveOptions {
+            max_depth: Some(7),
+            defender_hand: DefenderHand::AsGiven,
+            ..SolveOptions::default()
+        };
+
+        let report = solve_threatmate(&board, &options);
+        assert_eq!(
+            report.result.moves().map(|m| m[0]),
+            Some(Move::FromHand(P(1, 0), PIECE_ROOK.as_first()))
+        );
+
+        let report = analyze_threatmate(&board, &options);
+        assert_eq!(report.replies.len(), 6);
+        assert_eq!(report.stopped, None);
+        let escapes = report
+            .replies
+            .iter()
+            .filter(|reply| !reply.keeps_threat())
+            .map(|reply| reply.mv)
+            .collect::<Vec<_>>();
+        assert_eq!(escapes, vec![Move::NoPromotion(P(0, 0), P(0, 1))]);
+
+        // the mate gets shorter unless the gold stays next to the king
+        let delayed = report
+            .replies
+            .iter()
+            .filter(|reply| reply.result.moves().is_some_and(|m| m.len() == 3))
+            .count();
+        assert_eq!(delayed, 2);
+
+        // no threat while the defender is in check
+        board.set_sided_piece(P(1, 0), PIECE_GOLD.as_first());
+        let report = analyze_threatmate(&board, &options);
+        assert!(matches!(report.result, SolveResult::InvalidPosition(_)));
+        assert!(report.replies.is_empty());
+
+        let cancel = CancelHandle::new();
+        cancel.cancel();
+        let options = SolveOptions {
+            cancel: Some(cancel),
+            ..options
+        };
+        board.set_sided_piece(P(1, 0), EMPTY_CELL);
+        let report = analyze_threatmate(&board, &options);
+        assert_eq!(report.stopped, Some(StopReason::Cancelled));
+    }
+}
@@ -0,0 +1,378 @@
+//! Transposition table with a bounded memory, shared by the solvers.
+//!
+//! The table is split into buckets of `WAYS` slots. A key is stored in the bucket chosen by the
+//! key modulo the number of buckets, and when the bucket is full, the entry with the lowest priority is replaced.
+
+use std::mem::size_of;
+use std::sync::Mutex;
+
+/// Number of slots in a bucket.
+const WAYS: usize = 4;
+
+/// Default memory size of a table in bytes.
+pub const DEFAULT_TT_MEMORY: usize = 16 << 20;
+
+/// Salt for the hash of positions where the second player is to move,
+/// so that they don't share entries with the same boards where the first player is to move.
+pub(crate) const AND_NODE_SALT: u64 = 0x5bd1e9955bd1e995;
+
+/// Values stored in a `TranspositionTable`. They shouldn't own memory on the heap, which isn't
+/// counted in the memory of the table.
+pub trait TTValue: Clone {
+    /// Priority of the entry for replacement. Entries with lower priorities are replaced first,
+    /// so deeper or more expensive results should have higher priorities.
+    fn priority(&self) -> u64;
+}
+
+/// Statistics of a `TranspositionTable`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TTStats {
+    /// Number of lookups.
+    pub probes: u64,
+    /// Number of lookups which found an entry.
+    pub hits: u64,
+    /// Number of lookups which missed in a bucket full of entries for other keys.
+    pub collisions: u64,
+    /// Number of stores.
+    pub stores: u64,
+    /// Number of entries evicted to store other keys.
+    pub replacements: u64,
+}
+
+pub struct TranspositionTable<V> {
+    slots: Vec<Option<(u64, V)>>,
+    len: usize,
+    stats: TTStats,
+}
+
+impl<V: TTValue> TranspositionTable<V> {
+    /// Creates a table using at most about `memory` bytes for its slots.
+    pub fn new(memory: usize) -> TranspositionTable<V> {
+        let buckets = (memory / (size_of::<Option<(u64, V)>>() * WAYS)).max(1);
+        TranspositionTable {
+            slots: vec![None; buckets * WAYS],
+            len: 0,
+            stats: TTStats::default(),
+        }
+    }
+
+    fn bucket(&self, key: u64) -> std::ops::Range<usize> {
+        let buckets = self.slots.len() / WAYS;
+        let start = (key % buckets as u64) as usize * WAYS;
+        start..(start + WAYS)
+    }
+
+    pub fn get(&mut self, key: u64) -> Option<&V> {
+        self.find(key, |_| true)
+    }
+
+    /// Returns an entry for `key` satisfying `pred`.
+    /// A key can have several entries, e.g. for positions which differ only in the hands.
+    pub fn find(&mut self, key: u64, mut pred: impl FnMut(&V) -> bool) -> Option<&V> {
+        self.stats.probes += 1;
+        let range = self.bucket(key);
+        let bucket = &self.slots[range];
+        let ret = bucket
+            .iter()
+            .flatten()
+            .find(|(k, v)| *k == key && pred(v))
+            .map(|(_, v)| v);
+        if ret.is_some() {
+            self.stats.hits += 1;
+        } else if bucket.iter().all(|s| s.is_some()) {
+            self.stats.collisions += 1;
+        }
+        ret
+    }
+
+    /// Stores `value` for `key`, replacing the entry with the lowest priority if the bucket is full.
+    pub fn insert(&mut self, key: u64, value: V) {
+        self.insert_by(key, value, |_| true)
+    }
+
+    /// Same as `insert`, but only the entry for `key` satisfying `same` is overwritten,
+    /// and the other entries for `key` are kept.
+    pub fn insert_by(&mut self, key: u64, value: V, mut same: impl FnMut(&V) -> bool) {
+        self.stats.stores += 1;
+        let range = self.bucket(key);
+        let bucket = &mut self.slots[range];
+
+        if let Some(slot) = bucket.iter_mut().find(|s| match s {
+            Some((k, v)) => *k == key && same(v),
+            None => false,
+        }) {
+            *slot = Some((key, value));
+            return;
+        }
+        if let Some(slot) = bucket.iter_mut().find(|s| s.is_none()) {
+            *slot = Some((key, value));
+            self.len += 1;
+            return;
+        }
+
+        self.stats.replacements += 1;
+        let victim = bucket
+            .iter_mut()
+            .min_by_key(|s| s.as_ref().map_or(0, |(_, v)| v.priority()))
+            .unwrap();
+        *victim = Some((key, value));
+    }
+
+    /// Number of entries in the table.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Maximum number of entries in the table.
+    pub fn capacity(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn stats(&self) -> TTStats {
+        self.stats
+    }
+
+    /// Returns the keys and the values of all entries.
+    pub fn entries(&self) -> impl Iterator<Item = (u64, &V)> {
+        self.slots.iter().flatten().map(|(k, v)| (*k, v))
+    }
+
+    pub fn clear(&mut self) {
+        for slot in &mut self.slots {
+            *slot = None;
+        }
+        self.len = 0;
+        self.stats = TTStats::default();
+    }
+}
+
+impl<V: TTValue> Default for TranspositionTable<V> {
+    fn default() -> TranspositionTable<V> {
+        TranspositionTable::new(DEFAULT_TT_MEMORY)
+    }
+}
+
+/// Access to a transposition table by a search, either owned by it or shared among threads.
+/// The predicates are as in `TranspositionTable::find` and `TranspositionTable::insert_by`.
+pub trait TTAccess<V> {
+    fn probe(&mut self, key: u64, pred: &mut dyn FnMut(&V) -> bool) -> Option<V>;
+    fn store(&mut self, key: u64, value: V, same: &mut dyn FnMut(&V) -> bool);
+    /// Fraction of the slots in use.
+    fn fill(&self) -> f64;
+    fn entries(&self) -> Vec<(u64, V)>;
+}
+
+impl<V: TTValue> TTAccess<V> for TranspositionTable<V> {
+    fn probe(&mut self, key: u64, pred: &mut dyn FnMut(&V) -> bool) -> Option<V> {
+        self.find(key, pred).cloned()
+    }
+    fn store(&mut self, key: u64, value: V, same: &mut dyn FnMut(&V) -> bool) {
+        self.insert_by(key, value, same)
+    }
+    fn fill(&self) -> f64 {
+        self.len() as f64 / self.capacity().max(1) as f64
+    }
+    fn entries(&self) -> Vec<(u64, V)> {
+        TranspositionTable::entries(self)
+            .map(|(k, v)| (k, v.clone()))
+            .collect()
+    }
+}
+
+/// Transposition table shared among threads.
+/// It is split into shards, each of which is locked separately.
+pub struct ShardedTranspositionTable<V> {
+    shards: Vec<Mutex<TranspositionTable<V>>>,
+}
+
+impl<V: TTValue> ShardedTranspositionTable<V> {
+    /// Creates a table with `shards` shards using at most about `memory` bytes in total.
+    pub fn new(memory: usize, shards: usize) -> ShardedTranspositionTable<V> {
+        let shards = shards.max(1);
+        ShardedTranspositionTable {
+            shards: (0..shards)
+                .map(|_| Mutex::new(TranspositionTable::new(memory / shards)))
+                .collect(),
+        }
+    }
+
+    fn shard(&self, key: u64) -> &Mutex<TranspositionTable<V>> {
+        // the high bits, since the low bits choose the bucket in the shard
+        &self.shards[((key >> 40) % self.shards.len() as u64) as usize]
+    }
+
+    pub fn get(&self, key: u64) -> Option<V> {
+        self.shard(key).lock().unwrap().get(key).cloned()
+    }
+
+    pub fn find(&self, key: u64, pred: impl FnMut(&V) -> bool) -> Option<V> {
+        self.shard(key).lock().unwrap().find(key, pred).cloned()
+    }
+
+    pub fn insert(&self, key: u64, value: V) {
+        self.shard(key).lock().unwrap().insert(key, value)
+    }
+
+    pub fn insert_by(&self, key: u64, value: V, same: impl FnMut(&V) -> bool) {
+        self.shard(key).lock().unwrap().insert_by(key, value, same)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|s| s.lock().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|s| s.lock().unwrap().capacity())
+            .sum()
+    }
+
+    /// Returns the keys and the values of all entries.
+    /// The shards are locked one by one, so other threads may update the table meanwhile.
+    pub fn entries(&self) -> Vec<(u64, V)> {
+        let mut ret = vec![];
+        for shard in &self.shards {
+            let shard = shard.lock().unwrap();
+            ret.extend(shard.entries().map(|(k, v)| (k, v.clone())));
+        }
+        ret
+    }
+
+    /// Returns the sum of the statistics of the shards.
+    pub fn stats(&self) -> TTStats {
+        let mut ret = TTStats::default();
+        for shard in &self.shards {
+            let stats = shard.lock().unwrap().stats();
+            ret.probes += stats.probes;
+            ret.hits += stats.hits;
+            ret.collisions += stats.collisions;
+            ret.stores += stats.stores;
+            ret.replacements += stats.replacements;
+        }
+        ret
+    }
+}
+
+impl<V: TTValue> TTAccess<V> for &ShardedTranspositionTable<V> {
+    fn probe(&mut self, key: u64, pred: &mut dyn FnMut(&V) -> bool) -> Option<V> {
+        self.find(key, pred)
+    }
+    fn store(&mut self, key: u64, value: V, same: &mut dyn FnMut(&V) -> bool) {
+        self.insert_by(key, value, same)
+    }
+    fn fill(&self) -> f64 {
+        self.len() as f64 / self.capacity().max(1) as f64
+    }
+    fn entries(&self) -> Vec<(u64, V)> {
+        ShardedTranspositionTable::entries(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    impl TTValue for u64 {
+        fn priority(&self) -> u64 {
+            *self
+        }
+    }
+
+    #[test]
+    fn test_tt() {
+        // a single bucket
+        let mut tt = TranspositionTable::<u64>::new(0);
+        assert_eq!(tt.capacity(), WAYS);
+
+        for key in 0..(WAYS as u64) {
+            tt.insert(key, 10 + key);
+        }
+        assert_eq!(tt.len(), WAYS);
+        assert_eq!(tt.get(1), Some(&11));
+        assert_eq!(tt.get(100), None);
+
+        // overwriting the same key doesn't evict other entries
+        tt.insert(1, 5);
+        assert_eq!(tt.get(1), Some(&5));
+        assert_eq!(tt.stats().replacements, 0);
+
+        // the entry with the lowest priority (key 1) is replaced
+        tt.insert(100, 20);
+        assert_eq!(tt.get(1), None);
+        assert_eq!(tt.get(100), Some(&20));
+        assert_eq!(tt.get(0), Some(&10));
+        assert_eq!(tt.len(), WAYS);
+        assert_eq!(tt.entries().count(), WAYS);
+
+        assert_eq!(
+            tt.stats(),
+            TTStats {
+                probes: 6,
+                hits: 4,
+                collisions: 2,
+                stores: WAYS as u64 + 2,
+                replacements: 1,
+            }
+        );
+
+        tt.clear();
+        assert!(tt.is_empty());
+        assert_eq!(tt.get(0), None);
+    }
+
+    #[test]
+    fn test_tt_find() {
+        // entries for the same key distinguished by the parity
+        let mut tt = TranspositionTable::<u64>::new(0);
+        tt.insert_by(1, 10, |v| v % 2 == 0);
+        tt.insert_by(1, 11, |v| v % 2 == 1);
+        tt.insert_by(1, 12, |v| v % 2 == 0);
+        assert_eq!(tt.len(), 2);
+        assert_eq!(tt.find(1, |v| v % 2 == 0), Some(&12));
+        assert_eq!(tt.find(1, |v| v % 2 == 1), Some(&11));
+        assert_eq!(tt.find(1, |v| *v > 20), None);
+        assert_eq!(tt.find(2, |_| true), None);
+    }
+
+    #[test]
+    fn test_sharded_tt() {
+        let tt = ShardedTranspositionTable::<u64>::new(1 << 20, 4);
+        std::thread::scope(|s| {
+            for t in 0..4u64 {
+                let tt = &tt;
+                s.spawn(move || {
+                    for i in 0..100u64 {
+                        let key = (i << 40) | t;
+                        tt.insert(key, i);
+                    }
+                });
+            }
+        });
+        assert_eq!(tt.len(), 400);
+        assert_eq!(tt.get((7 << 40) | 2), Some(7));
+        assert_eq!(tt.stats().stores, 400);
+        assert!(tt.capacity() * size_of::<Option<(u64, u64)>>() <= 1 << 20);
+    }
+
+    #[test]
+    fn test_tt_memory() {
+        let tt = TranspositionTable::<u64>::new(1 << 20);
+        assert!(tt.capacity() * size_of::<Option<(u64, u64)>>() <= 1 << 20);
+        assert!(tt.capacity() * size_of::<Option<(u64, u64)>>() * 2 > 1 << 20);
+
+        // the entries of the solvers take no memory besides their slots
+        use super::super::{dfpn::DfpnEntry, MinimaxEntry};
+        assert!(!std::mem::needs_drop::<MinimaxEntry>());
+        assert!(!std::mem::needs_drop::<DfpnEntry>());
+    }
+}
@@ -0,0 +1,150 @@
+//! Detection of alternative solutions (yozume).
+
+use super::tt::TranspositionTable;
+use super::{
+    intermediate_boards, solve_with_options, with_defender_hand, Minimax, SolveOptions,
+    SolveResult, StopReason,
+};
+use crate::board::Board;
+use crate::common::*;
+
+/// An attacker move other than the main line which also mates within the remaining length.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Alternative {
+    /// Index of the main line move which `mv` replaces.
+    pub ply: usize,
+    pub mv: Move,
+    /// Length of the mate starting with `mv`.
+    pub length: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct YozumeReport {
+    /// Result of solving the problem. The alternatives are searched only if it is a mate.
+    pub result: SolveResult,
+    /// Alternatives for the attacker moves except the last one.
+    pub alternatives: Vec<Alternative>,
+    /// Alternatives for the last move, which are tolerated by convention.
+    pub final_alternatives: Vec<Alternative>,
+    /// Why the search of the alternatives stopped, if it didn't finish.
+    pub stopped: Option<StopReason>,
+}
+
+impl YozumeReport {
+    /// Whether the solution is unique except for the last move.
+    pub fn is_unique(&self) -> bool {
+        matches!(self.result, SolveResult::Mate { .. })
+            && self.alternatives.is_empty()
+            && self.stopped.is_none()
+    }
+}
+
+/// Solves the problem and searches alternatives for each attacker move on the main line.
+pub fn analyze_yozume(board: &Board, options: &SolveOptions) -> YozumeReport {
+    let result = solve_with_options(board, options).result;
+    let mut report = YozumeReport {
+        result,
+        alternatives: vec![],
+        final_alternatives: vec![],
+        stopped: None,
+    };
+    let moves = match &report.result {
+        SolveResult::Mate { moves, .. } => moves.clone(),
+        _ => return report,
+    };
+
+    let board = with_defender_hand(board, options.defender_hand);
+    let mut tt = TranspositionTable::new(options.tt_memory);
+    let mut minimax = Minimax::new(options, &mut tt);
+    // the lengths of the alternatives are reported
+    minimax.exact = true;
+    let boards = intermediate_boards(&board, &moves);
+
+    for ply in (0..moves.len()).step_by(2) {
+        let remaining = (moves.len() - ply) as i32;
+        minimax.root_depth = remaining;
+        for mv in boards[ply].enumerate_check() {
+            if mv == moves[ply] {
+                continue;
+            }
+            let mut child = boards[ply].clone();
+            child.apply_move(mv);
+            let line = minimax.solve_second(&child, remaining - 1);
+            if let Some(reason) = minimax.stopped {
+                report.stopped = Some(reason);
+                return report;
+            }
+            if let Some(line) = line {
+                let alternative = Alternative {
+                    ply,
+                    mv,
+                    length: line.len() + 1,
+                };
+                if remaining == 1 {
+                    report.final_alternatives.push(alternative);
+                } else {
+                    report.alternatives.push(alternative);
+                }
+            }
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::P;
+
+    #[test]
+    fn test_yozume() {
+        // unique except the last move, where ▲２二金 also mates
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 7), PIECE_KING.as_second());
+        board.set_sided_piece(P(1, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(2, 7), PIECE_GOLD.as_first());
+
+        let report = analyze_yozume(&board, &SolveOptions::default());
+        assert!(report.is_unique());
+        assert_eq!(
+            report.final_alternatives,
+            vec![Alternative {
+                ply: 2,
+                mv: Move::NoPromotion(P(2, 7), P(1, 7)),
+                length: 1,
+            }]
+        );
+
+        // ▲８九飛成 mates in 3 as well as ▲８七飛成
+        let mut board = Board::new();
+        board.set_sided_piece(P(7, 0), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 1), PIECE_ROOK.as_first());
+        board.set_sided_piece(P(7, 2), PIECE_SILVER.as_first());
+
+        let report = analyze_yozume(&board, &SolveOptions::default());
+        assert!(!report.is_unique());
+        assert_eq!(
+            report.result.moves().map(|m| m[0]),
+            Some(Move::Promotion(P(0, 1), P(6, 1)))
+        );
+        assert_eq!(
+            report.alternatives,
+            vec![Alternative {
+                ply: 0,
+                mv: Move::Promotion(P(0, 1), P(8, 1)),
+                length: 3,
+            }]
+        );
+        assert_eq!(report.final_alternatives.len(), 1);
+
+        let options = SolveOptions {
+            max_depth: Some(1),
+            ..SolveOptions::default()
+        };
+        let report = analyze_yozume(&board, &options);
+        assert!(!report.is_unique());
+        assert!(report.alternatives.is_empty());
+    }
+}
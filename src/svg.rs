@@ -0,0 +1,245 @@
+//! Rendering boards as SVG diagrams.
+
+use crate::board::Board;
+use crate::bod::HAND_ORDER;
+use crate::common::*;
+use crate::notation::{kanji_number, FILE_NAMES, RANK_NAMES};
+use crate::solver::intermediate_boards;
+use crate::P;
+use std::fmt::Write;
+
+/// Size of a cell in pixels.
+const CELL: i32 = 40;
+/// Width of the areas for hands on both sides of the board.
+const HAND_WIDTH: i32 = 40;
+/// Width of the margin around the board, where file and rank names are written.
+const MARGIN: i32 = 24;
+
+const HIGHLIGHT_COLOR: &str = "#ffe08a";
+const PROMOTED_COLOR: &str = "#c00000";
+
+/// Renders `board` as an SVG image.
+/// If `last_move` is given, its source and destination cells are highlighted.
+pub fn render_svg(board: &Board, last_move: Option<Move>) -> String {
+    let board_left = HAND_WIDTH + MARGIN;
+    let board_top = MARGIN;
+    let board_size = CELL * BOARD_SIZE;
+    let width = board_left + board_size + MARGIN + HAND_WIDTH;
+    let height = board_top + board_size + MARGIN;
+
+    let mut ret = String::new();
+    let _ = writeln!(
+        ret,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="serif">"#,
+        w = width,
+        h = height
+    );
+    let _ = writeln!(
+        ret,
+        r#"<rect x="0" y="0" width="{}" height="{}" fill="white"/>"#,
+        width, height
+    );
+
+    if let Some(mv) = last_move {
+        for pos in mv.src().into_iter().chain(std::iter::once(mv.dest())) {
+            let _ = writeln!(
+                ret,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+                board_left + pos.x() * CELL,
+                board_top + pos.y() * CELL,
+                CELL,
+                CELL,
+                HIGHLIGHT_COLOR
+            );
+        }
+    }
+
+    // grid
+    let _ = writeln!(
+        ret,
+        r#"<rect x="{}" y="{}" width="{s}" height="{s}" fill="none" stroke="black" stroke-width="2"/>"#,
+        board_left,
+        board_top,
+        s = board_size
+    );
+    for i in 1..BOARD_SIZE {
+        let _ = writeln!(
+            ret,
+            r#"<line x1="{x}" y1="{}" x2="{x}" y2="{}" stroke="black"/>"#,
+            board_top,
+            board_top + board_size,
+            x = board_left + i * CELL
+        );
+        let _ = writeln!(
+            ret,
+            r#"<line x1="{}" y1="{y}" x2="{}" y2="{y}" stroke="black"/>"#,
+            board_left,
+            board_left + board_size,
+            y = board_top + i * CELL
+        );
+    }
+    for x in 0..BOARD_SIZE {
+        let _ = writeln!(
+            ret,
+            r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+            board_left + x * CELL + CELL / 2,
+            board_top - MARGIN / 2,
+            MARGIN * 2 / 3,
+            FILE_NAMES[(BOARD_SIZE - 1 - x) as usize]
+        );
+    }
+    for y in 0..BOARD_SIZE {
+        let _ = writeln!(
+            ret,
+            r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+            board_left + board_size + MARGIN / 2,
+            board_top + y * CELL + CELL / 2,
+            MARGIN * 2 / 3,
+            RANK_NAMES[y as usize]
+        );
+    }
+
+    // pieces
+    for y in 0..BOARD_SIZE {
+        for x in 0..BOARD_SIZE {
+            let piece = board.get_sided_piece(P(y, x));
+            if piece.is_empty() {
+                continue;
+            }
+            let cx = board_left + x * CELL + CELL / 2;
+            let cy = board_top + y * CELL + CELL / 2;
+            let color = if piece.to_piece().0 >= 8 {
+                PROMOTED_COLOR
+            } else {
+                "black"
+            };
+            let rotate = if piece.is_second() {
+                format!(r#" transform="rotate(180 {} {})""#, cx, cy)
+            } else {
+                String::new()
+            };
+            let _ = writeln!(
+                ret,
+                r#"<text x="{}" y="{}" font-size="{}" fill="{}" text-anchor="middle" dominant-baseline="central"{}>{}</text>"#,
+                cx,
+                cy,
+                CELL * 3 / 4,
+                color,
+                rotate,
+                piece.to_piece().get_name()
+            );
+        }
+    }
+
+    // hands: the second player's on the top left and the first player's on the bottom right
+    write_hand(
+        &mut ret,
+        &hand_text('☖', |piece| board.get_second_hand(piece)),
+        HAND_WIDTH / 2,
+        board_top,
+        false,
+    );
+    write_hand(
+        &mut ret,
+        &hand_text('☗', |piece| board.get_first_hand(piece)),
+        width - HAND_WIDTH / 2,
+        board_top + board_size,
+        true,
+    );
+
+    ret.push_str("</svg>\n");
+    ret
+}
+
+fn hand_text(mark: char, hand: impl Fn(Piece) -> i8) -> String {
+    let mut ret = String::new();
+    ret.push(mark);
+    ret.push_str("持駒");
+    let mut empty = true;
+    for &piece in &HAND_ORDER {
+        let n = hand(piece);
+        if n == 0 {
+            continue;
+        }
+        empty = false;
+        ret.push(piece.get_name());
+        if n > 1 {
+            ret.push_str(&kanji_number(n as i32));
+        }
+    }
+    if empty {
+        ret.push_str("なし");
+    }
+    ret
+}
+
+/// Writes `text` vertically, starting at (`x`, `y`), or ending there if `from_bottom` is set.
+/// Characters are packed closer if the text is longer than the board.
+fn write_hand(out: &mut String, text: &str, x: i32, y: i32, from_bottom: bool) {
+    let n = text.chars().count() as i32;
+    let step = (HAND_WIDTH * 3 / 4).min(CELL * BOARD_SIZE / n);
+    let top = if from_bottom { y - step * n } else { y };
+    for (i, c) in text.chars().enumerate() {
+        let _ = writeln!(
+            out,
+            r#"<text x="{}" y="{}" font-size="{}" text-anchor="middle" dominant-baseline="central">{}</text>"#,
+            x,
+            top + step * i as i32 + step / 2,
+            step * 4 / 5,
+            c
+        );
+    }
+}
+
+/// Renders the board after each step of `steps`, starting from `board` itself.
+/// The move leading to each diagram is highlighted.
+pub fn render_solution_svg(board: &Board, steps: &[Move]) -> Vec<String> {
+    intermediate_boards(board, &steps.to_vec())
+        .iter()
+        .enumerate()
+        .map(|(i, b)| render_svg(b, if i == 0 { None } else { Some(steps[i - 1]) }))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_svg() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(0, 3), PIECE_PAWN.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_PROMOTED_ROOK.as_first());
+        board.set_first_hand(PIECE_GOLD, 2);
+
+        let svg = render_svg(&board, None);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("rotate(180").count(), 2);
+        assert!(svg.contains(&format!(r#"fill="{}""#, PROMOTED_COLOR)));
+        assert!(!svg.contains(HIGHLIGHT_COLOR));
+        // "☗持駒金二" and "☖持駒なし" are written one character at a time
+        assert!(svg.contains(">☗</text>") && svg.contains(">金</text>"));
+        assert!(svg.contains(">な</text>"));
+
+        let svg = render_svg(&board, Some(Move::NoPromotion(P(2, 4), P(1, 4))));
+        assert_eq!(svg.matches(HIGHLIGHT_COLOR).count(), 2);
+        let svg = render_svg(&board, Some(Move::FromHand(P(1, 4), PIECE_GOLD.as_first())));
+        assert_eq!(svg.matches(HIGHLIGHT_COLOR).count(), 1);
+    }
+
+    #[test]
+    fn test_render_solution_svg() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 4), PIECE_PAWN.as_first());
+        board.set_first_hand(PIECE_GOLD, 1);
+
+        let steps = vec![Move::FromHand(P(1, 4), PIECE_GOLD.as_first())];
+        let frames = render_solution_svg(&board, &steps);
+        assert_eq!(frames.len(), 2);
+        assert!(!frames[0].contains(HIGHLIGHT_COLOR));
+        assert!(frames[1].contains(HIGHLIGHT_COLOR));
+    }
+}
@@ -0,0 +1,211 @@
+use crate::board::Board;
+use crate::common::*;
+use crate::P;
+use std::fmt;
+
+/// Piece letters used for drops in USI, indexed by `Piece`.
+const USI_PIECE_LETTERS: [char; PIECE_TYPES] = ['P', 'L', 'N', 'S', 'G', 'B', 'R', 'K'];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UsiParseError {
+    /// The string is not of the form `7g7f`, `8h2b+` or `P*5e`.
+    Malformed,
+    /// There is no piece of the moving player at the source cell.
+    NoPiece,
+    /// The piece can't move to the destination cell.
+    IllegalMove,
+    /// The piece to be dropped is not in hand, or it can't be dropped on the destination
+    /// (an occupied cell, a cell where it can't move afterwards, or two pawns on a file).
+    IllegalDrop,
+}
+
+impl fmt::Display for UsiParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UsiParseError::Malformed => write!(f, "malformed USI move"),
+            UsiParseError::NoPiece => write!(f, "no piece at the source cell"),
+            UsiParseError::IllegalMove => write!(f, "the piece can't move to the destination"),
+            UsiParseError::IllegalDrop => write!(f, "the piece can't be dropped"),
+        }
+    }
+}
+
+impl std::error::Error for UsiParseError {}
+
+fn square_to_usi(pos: P) -> String {
+    format!("{}{}", BOARD_SIZE - pos.x(), (b'a' + pos.y() as u8) as char)
+}
+
+fn square_from_usi(file: char, rank: char) -> Option<P> {
+    let file = file.to_digit(10)? as i32;
+    if !(1..=BOARD_SIZE).contains(&file) || !('a'..='i').contains(&rank) {
+        return None;
+    }
+    Some(P(rank as i32 - 'a' as i32, BOARD_SIZE - file))
+}
+
+impl Move {
+    /// Converts the move to the USI format such as `7g7f`, `8h2b+` or `P*5e`.
+    pub fn to_usi(&self) -> String {
+        match *self {
+            Move::NoPromotion(src, dest) => {
+                format!("{}{}", square_to_usi(src), square_to_usi(dest))
+            }
+            Move::Promotion(src, dest) => {
+                format!("{}{}+", square_to_usi(src), square_to_usi(dest))
+            }
+            Move::FromHand(dest, piece) => format!(
+                "{}*{}",
+                USI_PIECE_LETTERS[piece.to_piece().0 as usize],
+                square_to_usi(dest)
+            ),
+        }
+    }
+
+    /// Parses a move in the USI format against `board`, where the second player moves if
+    /// `is_second`. USI doesn't tell which player moves, so it must be given.
+    pub fn from_usi(board: &Board, s: &str, is_second: bool) -> Result<Move, UsiParseError> {
+        let chars = s.trim().chars().collect::<Vec<_>>();
+        if chars.len() == 4 && chars[1] == '*' {
+            let piece = USI_PIECE_LETTERS[..(PIECE_TYPES - 1)]
+                .iter()
+                .position(|&c| c == chars[0])
+                .map(|i| Piece(i as i8))
+                .ok_or(UsiParseError::Malformed)?;
+            let dest = square_from_usi(chars[2], chars[3]).ok_or(UsiParseError::Malformed)?;
+            if !board.can_drop(dest, piece, is_second) {
+                return Err(UsiParseError::IllegalDrop);
+            }
+            let sided = if is_second {
+                piece.as_second()
+            } else {
+                piece.as_first()
+            };
+            return Ok(Move::FromHand(dest, sided));
+        }
+
+        let promotion = match chars.len() {
+            4 => false,
+            5 if chars[4] == '+' => true,
+            _ => return Err(UsiParseError::Malformed),
+        };
+        let src = square_from_usi(chars[0], chars[1]).ok_or(UsiParseError::Malformed)?;
+        let dest = square_from_usi(chars[2], chars[3]).ok_or(UsiParseError::Malformed)?;
+        let piece = board.get_sided_piece(src);
+        if (is_second && !piece.is_second()) || (!is_second && !piece.is_first()) {
+            return Err(UsiParseError::NoPiece);
+        }
+        let mv = if promotion {
+            Move::Promotion(src, dest)
+        } else {
+            Move::NoPromotion(src, dest)
+        };
+        if !board.enumerate_moves(src).contains(&mv) {
+            return Err(UsiParseError::IllegalMove);
+        }
+        Ok(mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_usi() {
+        assert_eq!(Move::NoPromotion(P(6, 2), P(5, 2)).to_usi(), "7g7f");
+        assert_eq!(Move::Promotion(P(7, 1), P(1, 7)).to_usi(), "8h2b+");
+        assert_eq!(
+            Move::FromHand(P(4, 4), PIECE_PAWN.as_first()).to_usi(),
+            "P*5e"
+        );
+        assert_eq!(
+            Move::FromHand(P(0, 8), PIECE_GOLD.as_second()).to_usi(),
+            "G*1a"
+        );
+    }
+
+    #[test]
+    fn test_from_usi() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(6, 7), PIECE_PAWN.as_first());
+        board.set_sided_piece(P(7, 1), PIECE_BISHOP.as_first());
+        board.set_first_hand(PIECE_PAWN, 1);
+        board.set_second_hand(PIECE_PAWN, 1);
+
+        assert_eq!(
+            Move::from_usi(&board, "2g2f", false),
+            Ok(Move::NoPromotion(P(6, 7), P(5, 7)))
+        );
+        assert_eq!(
+            Move::from_usi(&board, "8h2b+", false),
+            Ok(Move::Promotion(P(7, 1), P(1, 7)))
+        );
+        assert_eq!(
+            Move::from_usi(&board, "P*5e", false),
+            Ok(Move::FromHand(P(4, 4), PIECE_PAWN.as_first()))
+        );
+        assert_eq!(
+            Move::from_usi(&board, "2g2e", false),
+            Err(UsiParseError::IllegalMove)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "5e5d", false),
+            Err(UsiParseError::NoPiece)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "G*5e", false),
+            Err(UsiParseError::IllegalDrop)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "0a1a", false),
+            Err(UsiParseError::Malformed)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "X*5e", false),
+            Err(UsiParseError::Malformed)
+        );
+
+        // two pawns on a file, and a pawn which can't move afterwards
+        assert_eq!(
+            Move::from_usi(&board, "P*2e", false),
+            Err(UsiParseError::IllegalDrop)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "P*4a", false),
+            Err(UsiParseError::IllegalDrop)
+        );
+
+        // the side to move is given, even if the second player is not in check
+        assert_eq!(
+            Move::from_usi(&board, "5a5b", false),
+            Err(UsiParseError::NoPiece)
+        );
+        assert_eq!(
+            Move::from_usi(&board, "5a5b", true),
+            Ok(Move::NoPromotion(P(0, 4), P(1, 4)))
+        );
+        assert_eq!(
+            Move::from_usi(&board, "P*5e", true),
+            Ok(Move::FromHand(P(4, 4), PIECE_PAWN.as_second()))
+        );
+        assert_eq!(
+            Move::from_usi(&board, "P*4i", true),
+            Err(UsiParseError::IllegalDrop)
+        );
+    }
+
+    #[test]
+    fn test_usi_roundtrip() {
+        let mut board = Board::new();
+        board.set_sided_piece(P(0, 4), PIECE_KING.as_second());
+        board.set_sided_piece(P(2, 5), PIECE_GOLD.as_first());
+        board.set_sided_piece(P(3, 1), PIECE_ROOK.as_first());
+        board.set_first_hand(PIECE_SILVER, 1);
+
+        for &mv in &board.enumerate_check() {
+            assert_eq!(Move::from_usi(&board, &mv.to_usi(), false), Ok(mv));
+        }
+    }
+}